@@ -0,0 +1,125 @@
+/*
+ * What the CPU sees through its address and data pins.
+ * Implemented by the NES memory map (Nes), a flat 64 KiB memory for standalone 6502 programs,
+ * and LoggingBus which records the accesses of another bus.
+ */
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+
+    // Read without side effects, for debug output and tracing.
+    fn peek(&self, addr: u16) -> u8;
+
+    // Run the hardware other than the CPU for the given number of CPU cycles.
+    fn clock(&mut self, _cpu_cycle: usize) {}
+
+    // Interrupt lines. true means asserted.
+    fn nmi_line(&self) -> bool {
+        false
+    }
+
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    // The page of a DMA to OAM which has been requested since the last call, if any.
+    // The CPU halts and does the transfer itself.
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+const FLAT_MEMORY_SIZE: usize = 0x10000;
+
+// 64 KiB of RAM without any devices, for 6502 test programs.
+pub struct FlatMemory {
+    memory: Vec<u8>,
+}
+
+impl Default for FlatMemory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlatMemory {
+    pub fn new() -> Self {
+        Self {
+            memory: vec![0; FLAT_MEMORY_SIZE],
+        }
+    }
+
+    // Copy `data` into the memory starting at `addr`.
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        let start = addr as usize;
+        self.memory[start..start + data.len()].copy_from_slice(data);
+    }
+}
+
+impl Bus for FlatMemory {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// Wraps another bus and records every access to it, in order.
+pub struct LoggingBus<B: Bus> {
+    pub inner: B,
+    pub log: Vec<Access>,
+}
+
+impl<B: Bus> LoggingBus<B> {
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            log: vec![],
+        }
+    }
+}
+
+impl<B: Bus> Bus for LoggingBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.inner.read(addr);
+        self.log.push(Access::Read(addr, data));
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data);
+        self.log.push(Access::Write(addr, data));
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.inner.peek(addr)
+    }
+
+    fn clock(&mut self, cpu_cycle: usize) {
+        self.inner.clock(cpu_cycle);
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.inner.nmi_line()
+    }
+
+    fn irq_line(&self) -> bool {
+        self.inner.irq_line()
+    }
+
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        self.inner.take_oam_dma()
+    }
+}
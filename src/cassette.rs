@@ -10,12 +10,29 @@ const CHR_ROM_UNIT_SIZE: usize = 0x2000; // 8192 bytes
 
 const PRG_ROM_MAX_SIZE: usize = 0x8000;
 
+// Flags 6
+const FLAGS6_VERTICAL_MIRRORING: u8 = 0b0000_0001;
+const FLAGS6_TRAINER: u8 = 0b0000_0100;
+const FLAGS6_FOUR_SCREEN: u8 = 0b0000_1000;
+
+// How the four nametables of the PPU map onto VRAM.
+// https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mirroring {
+    Horizontal, // $2000 = $2400, $2800 = $2C00, for vertical scrolling
+    Vertical, // $2000 = $2800, $2400 = $2C00, for horizontal scrolling
+    SingleScreenA, // All of them are the first 1 KiB
+    SingleScreenB, // All of them are the second 1 KiB
+    FourScreen, // The cartridge has 2 KiB more VRAM
+}
+
 pub struct Cassette {
     header: [u8; INES_HEADER_SIZE],
     // trainer: Option<[u8; TRAINER_SIZE]>,
     pub prg_rom: [u8; PRG_ROM_MAX_SIZE],
     pub chr_rom: Vec<u8>,
     pub sprites: Vec<Sprite>,
+    mirroring: Mirroring,
 }
 
 impl Cassette {
@@ -25,27 +42,33 @@ impl Cassette {
             prg_rom: [0; PRG_ROM_MAX_SIZE],
             chr_rom: vec![],
             sprites: vec![],
+            mirroring: Mirroring::Horizontal,
         };
 
         // Parse header
-        for i in 0..INES_HEADER_SIZE {
-            cassette.header[i] = data[i];
-        }
+        cassette.header.copy_from_slice(&data[..INES_HEADER_SIZE]);
 
         // Trainer, if present
-        if data[6] & 0b00000100 == 0b00000100 {
+        if data[6] & FLAGS6_TRAINER == FLAGS6_TRAINER {
             panic!("Trainer is not implement yet");
         }
 
+        cassette.mirroring = if data[6] & FLAGS6_FOUR_SCREEN == FLAGS6_FOUR_SCREEN {
+            Mirroring::FourScreen
+        } else if data[6] & FLAGS6_VERTICAL_MIRRORING == FLAGS6_VERTICAL_MIRRORING {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        debug!("Mirroring = {:?}", cassette.mirroring);
+
         // Parse PRG ROM data
         let prg_start = INES_HEADER_SIZE;
         let prg_end = prg_start + PRG_ROM_UNIT_SIZE * (cassette.header[4] as usize);
         debug!("PRG ROM size = {} units ({} bytes)", cassette.header[4], prg_end - prg_start);
         debug!("PRG ROM start address = 0x{:X}", prg_start);
         debug!("PRG ROM end address = 0x{:X}", prg_end);
-        for i in prg_start..prg_end {
-            cassette.prg_rom[i-prg_start] = data[i];
-        }
+        cassette.prg_rom[..prg_end-prg_start].copy_from_slice(&data[prg_start..prg_end]);
 
         let chr_start = prg_end;
         let chr_end = chr_start + CHR_ROM_UNIT_SIZE * (cassette.header[5] as usize);
@@ -59,7 +82,7 @@ impl Cassette {
         cassette
     }
 
-    fn parse_sprites(chr_rom: &Vec<u8>) -> Vec<Sprite> {
+    fn parse_sprites(chr_rom: &[u8]) -> Vec<Sprite> {
         let mut sprites = Vec::new();
         for i in 0..(chr_rom.len()/16) {
             sprites.push(Sprite::new(&chr_rom[i*16..i*16+16]));
@@ -67,6 +90,33 @@ impl Cassette {
         sprites
     }
 
+    pub fn read_chr_rom(&self, addr: u16) -> u8 {
+        self.chr_rom[addr as usize]
+    }
+
+    pub fn get_sprite(&self, id: u8) -> &Sprite {
+        &self.sprites[id as usize]
+    }
+
+    // A CPU write to 0x4020-0xFFFF, where mappers have their registers.
+    // NROM has none, and its PRG ROM ignores writes.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        debug!("Ignored write to cartridge space: 0x{:X} = 0x{:02X}", addr, data);
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    // Mappers with mirroring control switch it at runtime.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    pub fn prg_rom_size(&self) -> usize {
+        PRG_ROM_UNIT_SIZE * (self.header[4] as usize)
+    }
+
     pub fn is_ines(&self) -> bool {
         self.header[0..4] == INES_HEADER_CONSTANT
     }
@@ -81,6 +131,7 @@ pub struct Sprite {
 }
 
 impl Sprite {
+    #[allow(clippy::needless_range_loop)]
     pub fn new(data: &[u8]) -> Self {
         let mut sprite = [[0; 8]; 8];
         for y in 0..SPRITE_HEIGHT {
@@ -1,10 +1,16 @@
 use super::instruction::Instruction;
 use super::instruction::Opcode;
 use super::instruction::Addressing;
-use super::nes::Nes;
+use super::bus::Bus;
+use super::disasm;
 
-const RAM_SIZE: usize = 0x0800;
-const PRG_ROM_BASE: u16 = 0x8000;
+const STACK_BASE: u16 = 0x0100;
+
+// OAM DMA copies a page into OAM by writing to OAMDATA.
+const OAMDATA: u16 = 0x2004;
+
+// Constant used by the unstable XAA and LAX #imm. $EE is the most common value on real 2A03s.
+const UNSTABLE_MAGIC: u8 = 0xEE;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Flag {
@@ -15,6 +21,7 @@ pub enum Flag {
     Overflow,
     Negative,
     Break,
+    Unused, // Bit 5 always reads as 1
 }
 
 impl From<Flag> for u8 {
@@ -25,31 +32,73 @@ impl From<Flag> for u8 {
             Flag::InterruptDisable  => 0b00000100,
             Flag::Decimal           => 0b00001000,
             Flag::Break             => 0b00010000,
+            Flag::Unused            => 0b00100000,
             Flag::Overflow          => 0b01000000,
             Flag::Negative          => 0b10000000,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Interruption {
     RESET,
     IRQ,
     BRK,
     NMI,
-    None,
 }
 
+// Devices which can pull the shared IRQ line low. The line stays asserted while any of them holds it.
+#[derive(Debug, Clone, Copy)]
+pub enum IrqSource {
+    Mapper,
+    FrameCounter,
+    Dmc,
+}
+
+impl From<IrqSource> for u8 {
+    fn from(s: IrqSource) -> Self {
+        match s {
+            IrqSource::Mapper       => 0b00000001,
+            IrqSource::FrameCounter => 0b00000010,
+            IrqSource::Dmc          => 0b00000100,
+        }
+    }
+}
+
+// https://wiki.nesdev.com/w/index.php/CPU_interrupts
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE; // Shared with BRK
+
 pub struct Cpu {
     // Registers
     a: u8,
     x: u8,
     y: u8,
     pc: u16,
-    s: u16,
+    s: u8,
     status: u8, // P
 
-    ram: [u8; RAM_SIZE],
+    // Set by KIL. The CPU stops fetching instructions until it is reset.
+    halted: bool,
+
+    // Cycles since power-on. Every cycle is one bus access.
+    cycles: usize,
+    // Clock the other hardware on every bus access instead of after each instruction.
+    cycle_stepped: bool,
+
+    // NMI is edge triggered: the level seen at the last cycle, and whether an edge is waiting to be serviced.
+    nmi_line: bool,
+    nmi_pending: bool,
+    // Interrupts polled at the end of the last cycle and the one before it.
+    interrupt_poll: Option<Interruption>,
+    previous_interrupt_poll: Option<Interruption>,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
@@ -58,84 +107,255 @@ impl Cpu {
             a: 0,
             x: 0,
             y: 0,
-            pc: PRG_ROM_BASE,
-            s: 0x00fd,
+            pc: 0,
+            s: 0xfd,
             status: 0x34,
-            ram: [0; RAM_SIZE],
+            halted: false,
+            cycles: 0,
+            cycle_stepped: false,
+            nmi_line: false,
+            nmi_pending: false,
+            interrupt_poll: None,
+            previous_interrupt_poll: None,
+        }
+    }
+
+    pub fn set_cycle_stepped(&mut self, cycle_stepped: bool) {
+        self.cycle_stepped = cycle_stepped;
+    }
+
+    // https://wiki.nesdev.com/w/index.php/CPU_power_up_state
+    // Returns the number of cycles taken by the reset sequence.
+    pub fn power_on<B: Bus>(&mut self, bus: &mut B) -> usize {
+        self.a = 0;
+        self.x = 0;
+        self.y = 0;
+        self.s = 0;
+        self.status = 0x34;
+        self.cycles = 0;
+        self.nmi_line = false;
+        self.nmi_pending = false;
+        self.interrupt_poll = None;
+        self.previous_interrupt_poll = None;
+
+        // S starts at 0 and the reset sequence leaves it at 0xFD.
+        self.reset(bus)
+    }
+
+    // Soft reset, like the reset button. Registers other than S, P and PC and the RAM are kept.
+    // Returns the number of cycles taken by the reset sequence.
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) -> usize {
+        let start = self.cycles;
+        self.halted = false;
+        self.hardware_interrupt(bus, Interruption::RESET);
+
+        self.catch_up(bus, start)
+    }
+
+    // Cycles since power-on
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn s(&self) -> u8 {
+        self.s
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: u8) {
+        self.status = status;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    // Start from an address other than the RESET vector, as test programs do.
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    // Run one instruction, OAM DMA if the instruction started one, and the interrupt sequence if an
+    // interrupt was polled during them. Returns the number of cycles taken.
+    // Without cycle stepping, see catch_up() for what the other hardware sees of it.
+    pub fn tick<B: Bus>(&mut self, bus: &mut B) -> usize {
+        if self.halted {
+            return 0
+        }
+
+        let start = self.cycles;
+        self.execute_instruction(bus);
+
+        if let Some(page) = bus.take_oam_dma() {
+            self.oam_dma(bus, page);
+        }
+
+        if let Some(interruption) = self.previous_interrupt_poll {
+            if !self.halted {
+                self.hardware_interrupt(bus, interruption);
+            }
+        }
+
+        self.catch_up(bus, start)
+    }
+
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#OAM_DMA
+    // The CPU halts for a cycle, waits one more when the next cycle is a write cycle (an odd cycle),
+    // then reads and writes 256 times: 513 or 514 cycles in total.
+    fn oam_dma<B: Bus>(&mut self, bus: &mut B, page: u8) {
+        self.dummy_read(bus, self.pc);
+        if self.cycles % 2 == 1 {
+            self.dummy_read(bus, self.pc);
+        }
+
+        let base = (page as u16) << 8;
+        for offset in 0..=0xFF {
+            let data = self.read(bus, base | offset);
+            self.write(bus, OAMDATA, data);
         }
     }
 
-    pub fn tick(&mut self, nes: &mut Nes) -> usize {
-        let cycle = self.execute_instruction(nes);
-        self.interrupt(nes);
+    // Returns the number of cycles since `start`. Without cycle stepping, the other hardware
+    // catches up with the CPU here. Then every access of the instruction sees the PPU as it was
+    // before the instruction, and interrupt lines are polled against that state as well. So the
+    // timing of PPU registers within an instruction is not exact: a PPUSTATUS read on the dot
+    // VBlank starts doesn't suppress the flag and the NMI, and writes take effect on earlier dots.
+    fn catch_up<B: Bus>(&mut self, bus: &mut B, start: usize) -> usize {
+        let cycle = self.cycles - start;
+        if !self.cycle_stepped {
+            bus.clock(cycle);
+        }
+
         cycle
     }
 
-    fn interrupt(&mut self, nes: &mut Nes) {
-        match nes.cpu_interruption {
-            Interruption::RESET => { todo!("CPU RESET interruption is not implemented yet") },
-            Interruption::IRQ => { todo!("CPU IRQ interruption is not implemented yet") },
-            Interruption::BRK => {
-                if self.read_flag(Flag::InterruptDisable) {
-                    return
-                }
-                debug!("BRK interruption: Jump to 0x{:02X}{:02X}",
-                       self.read(nes, 0xFFFF), self.read(nes,0xFFFE));
-                self.push_word(self.pc);
-                self.push_byte(self.status);
-                self.status = self.status | u8::from(Flag::InterruptDisable);
+    fn detect_nmi_edge<B: Bus>(&mut self, bus: &B) {
+        let line = bus.nmi_line();
+        if line && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = line;
+    }
 
-                self.pc = (self.read(nes, 0xFFFF) as u16) << 8 | self.read(nes, 0xFFFE) as u16;
-            },
-            Interruption::NMI => { todo!("CPU NMI interruption is not implemented yet") },
-            Interruption::None => {},
+    // Interrupt lines are polled at the end of every cycle, and an instruction acts on the poll of its
+    // second-to-last cycle. This is why changes of I by CLI, SEI and PLP take effect after the next instruction.
+    // NMI has priority over IRQ, and IRQ is ignored while I is set.
+    fn poll_interrupts<B: Bus>(&mut self, bus: &B) {
+        self.detect_nmi_edge(bus);
+
+        self.previous_interrupt_poll = self.interrupt_poll;
+        self.interrupt_poll = if self.nmi_pending {
+            Some(Interruption::NMI)
+        } else if bus.irq_line() && !self.read_flag(Flag::InterruptDisable) {
+            Some(Interruption::IRQ)
+        } else {
+            None
+        };
+    }
+
+    // IRQ, NMI and RESET take the place of an opcode fetch. The CPU reads the next byte twice
+    // without incrementing PC, then runs the same sequence as BRK.
+    fn hardware_interrupt<B: Bus>(&mut self, bus: &mut B, interruption: Interruption) {
+        self.dummy_read(bus, self.pc);
+        self.dummy_read(bus, self.pc);
+        self.interrupt(bus, interruption);
+    }
+
+    // Push PC and P, then jump through the vector.
+    // PC must already point to the return address; BRK skips its padding byte before calling this.
+    fn interrupt<B: Bus>(&mut self, bus: &mut B, interruption: Interruption) {
+        if interruption == Interruption::RESET {
+            // RESET runs the same sequence with the writes turned into reads; only S moves.
+            for _ in 0..3 {
+                self.dummy_read_stack(bus);
+                self.s = self.s.wrapping_sub(1);
+            }
+        } else {
+            let mut status = self.status | u8::from(Flag::Unused);
+            if interruption == Interruption::BRK {
+                status |= u8::from(Flag::Break);
+            } else {
+                status &= !u8::from(Flag::Break);
+            }
+
+            self.push_word(bus, self.pc);
+            self.push_byte(bus, status);
         }
-        nes.cpu_interruption = Interruption::None;
+        self.write_flag(Flag::InterruptDisable, true);
+
+        // An NMI edge which arrives before the vector is fetched hijacks BRK and IRQ:
+        // they push their own state but continue at the NMI handler.
+        let vector = match interruption {
+            Interruption::RESET => RESET_VECTOR,
+            _ if self.nmi_pending => {
+                self.nmi_pending = false;
+                NMI_VECTOR
+            },
+            _ => IRQ_VECTOR,
+        };
+
+        let l = self.read(bus, vector) as u16;
+        let h = self.read(bus, vector + 1) as u16;
+        self.pc = h << 8 | l;
+        debug!("{:?} interruption: Jump to 0x{:04X}", interruption, self.pc);
     }
 
-    fn dump(&self) {
+    pub fn dump(&self) {
         println!("Cpu {{");
         println!("  a  = {:02X}", self.a);
         println!("  x  = {:02X}", self.x);
         println!("  y  = {:02X}", self.y);
         println!("  pc = {:04X}", self.pc);
-        println!("  s  = {:04X}", self.s);
+        println!("  s  = {:02X}", self.s);
         println!("  p  = {:08b}", self.status);
         println!("}}");
     }
 
-    // https://wiki.nesdev.com/w/index.php/CPU_memory_map
-    fn read(&mut self, nes: &mut Nes, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x07FF => self.read_ram(addr),
-            0x0800..=0x0FFF => self.read_ram(addr - 0x0800),
-            0x1000..=0x17FF => self.read_ram(addr - 0x1000),
-            0x1800..=0x1FFF => self.read_ram(addr - 0x1800),
-            0x2000..=0x2007 => nes.ppu_register_bus.cpu_read(addr),
-            0x2008..=0x401F => { warn!("Reading CPU address 0x2008-0x401F is not implemented"); 0 },
-            0x4020..=0x7FFF => { warn!("Reading CPU address 0x4020-0x7FFF is not implemented"); 0 }, // 拡張ROM, 拡張RAM
-            PRG_ROM_BASE..=0xFFFF => nes.read_program(addr-PRG_ROM_BASE),
+    // Every bus access takes one cycle. With cycle stepping, the other hardware runs
+    // for the cycle before the access.
+    fn clock<B: Bus>(&mut self, bus: &mut B) {
+        self.cycles += 1;
+        if self.cycle_stepped {
+            bus.clock(1);
         }
     }
 
-    fn write(&mut self, nes: &mut Nes, addr: u16, data: u8) {
-        match addr {
-            0x0000..=0x07FF => self.write_ram(addr, data),
-            0x0800..=0x0FFF => self.write_ram(addr - 0x0800, data),
-            0x1000..=0x17FF => self.write_ram(addr - 0x1000, data),
-            0x1800..=0x1FFF => self.write_ram(addr - 0x1800, data),
-            0x2000..=0x2007 => nes.ppu_register_bus.cpu_write(addr, data),
-            0x2008..=0x401F => warn!("Writing CPU address 0x2008-0x401F is not implemented"),
-            0x4020..=0xFFFF => panic!("Cartridge space is read only: 0x{:X}", addr),
-        }
+    fn read<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u8 {
+        self.clock(bus);
+        let data = bus.read(addr);
+        self.poll_interrupts(bus);
+
+        data
     }
 
-    fn read_ram(&self, addr: u16) -> u8 {
-        self.ram[addr as usize]
+    // A read whose data is thrown away. It still takes a cycle and has the side effects of a read.
+    fn dummy_read<B: Bus>(&mut self, bus: &mut B, addr: u16) {
+        self.read(bus, addr);
     }
 
-    fn write_ram(&mut self, addr: u16, data: u8) {
-        self.ram[addr as usize] = data;
+    fn write<B: Bus>(&mut self, bus: &mut B, addr: u16, data: u8) {
+        self.clock(bus);
+        bus.write(addr, data);
+        self.poll_interrupts(bus);
     }
 
     fn read_flag(&self, f: Flag) -> bool {
@@ -152,92 +372,192 @@ impl Cpu {
         }
     }
 
-    fn push_byte(&mut self, data: u8) {
-        self.write_ram(self.s, data);
+    fn push_byte<B: Bus>(&mut self, bus: &mut B, data: u8) {
+        self.write(bus, STACK_BASE + self.s as u16, data);
         self.s = self.s.wrapping_sub(1);
     }
 
-    fn push_word(&mut self, data: u16) {
-        self.push_byte((data >> 8) as u8);
-        self.push_byte((data & 0x00ff) as u8);
+    fn push_word<B: Bus>(&mut self, bus: &mut B, data: u16) {
+        self.push_byte(bus, (data >> 8) as u8);
+        self.push_byte(bus, (data & 0x00ff) as u8);
+    }
+
+    fn pop<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        self.read(bus, STACK_BASE + self.s as u16)
+    }
+
+    fn pop_word<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let l = self.pop(bus) as u16;
+        let h = self.pop(bus) as u16;
+        h << 8 | l
     }
 
-    fn pop(&mut self) -> u8 {
-        self.s += 1;
-        self.read_ram(self.s - 1)
+    // Pulling instructions spend a cycle reading the top of the stack before S is incremented.
+    fn dummy_read_stack<B: Bus>(&mut self, bus: &mut B) {
+        self.dummy_read(bus, STACK_BASE + self.s as u16);
     }
 
-    fn fetch_byte(&mut self, nes: &mut Nes) -> u8 {
+    fn fetch_byte<B: Bus>(&mut self, bus: &mut B) -> u8 {
         self.pc += 1;
-        self.read(nes, self.pc-1)
+        self.read(bus, self.pc-1)
     }
 
-    fn fetch_word(&mut self, nes: &mut Nes) -> u16 {
-        let l = self.fetch_byte(nes) as u16;
-        let h = self.fetch_byte(nes) as u16;
+    fn fetch_word<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let l = self.fetch_byte(bus) as u16;
+        let h = self.fetch_byte(bus) as u16;
         h << 8 | l
     }
 
-    // Return (address: Option<u16>, data: u8, is_page_crossed: bool)
-    // Accumulator and Immediate don't appear at same instruction.
-    fn fetch_addressed_data(&mut self, nes: &mut Nes, mode: &Addressing) -> (Option<u16>, u8, bool) {
+    // Read a little endian word from the zero page. The high byte wraps around within the page.
+    fn read_zero_page_word<B: Bus>(&mut self, bus: &mut B, addr: u8) -> u16 {
+        let l = self.read(bus, addr as u16) as u16;
+        let h = self.read(bus, addr.wrapping_add(1) as u16) as u16;
+        h << 8 | l
+    }
+
+    // Return (address: u16, is_page_crossed: bool)
+    // `store` is true for stores and read-modify-write instructions, which always spend
+    // the cycle for fixing the high byte of an indexed address.
+    fn fetch_operand_address<B: Bus>(&mut self, bus: &mut B, mode: &Addressing, store: bool) -> (u16, bool) {
         match mode {
-            Addressing::Implied => { (None, 0, false) },
-            Addressing::Accumulator => (None, self.a, false),
-            Addressing::Immediate => (None, self.fetch_byte(nes), false),
-            Addressing::ZeroPage => {
-                let addr = self.fetch_byte(nes) as u16;
-                (Some(addr), self.read(nes, addr), false)
-            },
-            Addressing::ZeroPageX => {
-                let addr = self.fetch_byte(nes) as u16 + self.x as u16;
-                (Some(addr), self.read(nes, addr), false) // If needed, calc is_page_crossed.
-            },
-            Addressing::ZeroPageY => { todo!("Not implemented Reative addressing mode") },
-            Addressing::Relative => { todo!("Not implemented Reative addressing mode") },
-            Addressing::Absolute => {
-                let addr = self.fetch_word(nes);
-                (Some(addr), self.read(nes, addr), false)
-            },
+            Addressing::ZeroPage => (self.fetch_byte(bus) as u16, false),
+            Addressing::ZeroPageX => (self.fetch_zero_page_indexed(bus, self.x), false),
+            Addressing::ZeroPageY => (self.fetch_zero_page_indexed(bus, self.y), false),
+            Addressing::Absolute => (self.fetch_word(bus), false),
             Addressing::AbsoluteX => {
-                let base = self.fetch_word(nes);
-                let addr = base.wrapping_add(self.x as u16);
-                (Some(addr), self.read(nes, addr), base & 0xff00 != addr & 0xff00)
+                let base = self.fetch_word(bus);
+                self.index_address(bus, base, self.x, store)
             },
             Addressing::AbsoluteY => {
-                let base = self.fetch_word(nes);
-                let addr = base.wrapping_add(self.y as u16);
-                (Some(addr), self.read(nes, addr), base & 0xff00 != addr & 0xff00)
+                let base = self.fetch_word(bus);
+                self.index_address(bus, base, self.y, store)
+            },
+            Addressing::Indirect => {
+                // The high byte is fetched without carrying into the page: JMP ($10FF) reads $10FF and $1000.
+                let ptr = self.fetch_word(bus);
+                let l = self.read(bus, ptr) as u16;
+                let h = self.read(bus, (ptr & 0xff00) | (ptr as u8).wrapping_add(1) as u16) as u16;
+                (h << 8 | l, false)
             },
-            Addressing::Indirect => { todo!("Not implemented Reative addressing mode") },
             Addressing::IndexedIndirect => {
-                let zero_page_addr = self.fetch_byte(nes).wrapping_add(self.x) as u16;
-                let l = self.read(nes, zero_page_addr) as u16;
-                let h = (self.read(nes, zero_page_addr.wrapping_add(1)) as u16) << 8;
-                let addr = l + h;
-                (Some(addr), self.read(nes, addr), false)
+                let zero_page_addr = self.fetch_zero_page_indexed(bus, self.x) as u8;
+                (self.read_zero_page_word(bus, zero_page_addr), false)
             },
             Addressing::IndirectIndexed => {
-                let zero_page_addr = self.fetch_byte(nes) as u16;
-                let l = self.read(nes, zero_page_addr) as u16;
-                let h = (self.read(nes, zero_page_addr.wrapping_add(1)) as u16) << 8;
-                let addr = l + h + self.y as u16;
-                (Some(addr), self.read(nes, addr), (l + h) & 0xff00 != addr & 0xff00)
+                let zero_page_addr = self.fetch_byte(bus);
+                let base = self.read_zero_page_word(bus, zero_page_addr);
+                self.index_address(bus, base, self.y, store)
+            },
+            _ => panic!("Addressing mode {:?} doesn't have an operand address", mode),
+        }
+    }
+
+    // The CPU reads the base address while adding the index to it.
+    fn fetch_zero_page_indexed<B: Bus>(&mut self, bus: &mut B, index: u8) -> u16 {
+        let base = self.fetch_byte(bus);
+        self.dummy_read(bus, base as u16);
+        base.wrapping_add(index) as u16
+    }
+
+    // The index is added to the low byte first, and the CPU reads from that address while the carry goes into
+    // the high byte. Loads use the read as it is when no page is crossed.
+    // Return (address: u16, is_page_crossed: bool)
+    fn index_address<B: Bus>(&mut self, bus: &mut B, base: u16, index: u8, store: bool) -> (u16, bool) {
+        let addr = base.wrapping_add(index as u16);
+        let page_crossed = base & 0xff00 != addr & 0xff00;
+        if page_crossed || store {
+            self.dummy_read(bus, (base & 0xff00) | (addr & 0x00ff));
+        }
+
+        (addr, page_crossed)
+    }
+
+    // Return (address: Option<u16>, data: u8, is_page_crossed: bool)
+    // Accumulator and Immediate don't appear at same instruction.
+    fn fetch_addressed_data<B: Bus>(&mut self, bus: &mut B, mode: &Addressing) -> (Option<u16>, u8, bool) {
+        match mode {
+            Addressing::Implied => { (None, 0, false) },
+            Addressing::Accumulator => (None, self.a, false),
+            Addressing::Immediate => (None, self.fetch_byte(bus), false),
+            _ => {
+                let (addr, page_crossed) = self.fetch_operand_address(bus, mode, false);
+                (Some(addr), self.read(bus, addr), page_crossed)
             },
-            Addressing::UNKNOWN => { panic!("Unknown addressing mode") },
         }
     }
 
-    fn branch_relative(&mut self, nes: &mut Nes, condition: bool) -> usize {
-        let data = self.fetch_byte(nes) as i8;
+    // Read-modify-write instructions such as ASL, INC or ROR.
+    // Returns the modified value.
+    fn modify<B: Bus, F>(&mut self, bus: &mut B, mode: &Addressing, f: F) -> u8
+    where
+        F: FnOnce(&mut Self, u8) -> u8,
+    {
+        if *mode == Addressing::Accumulator {
+            let result = f(self, self.a);
+            self.a = result;
+            return result
+        }
+
+        let (addr, _) = self.fetch_operand_address(bus, mode, true);
+        let data = self.read(bus, addr);
+        // The unmodified value is written back while the CPU is modifying it.
+        self.write(bus, addr, data);
+        let result = f(self, data);
+        self.write(bus, addr, result);
+
+        result
+    }
+
+    fn update_zero_and_negative_flags(&mut self, v: u8) {
+        self.write_flag(Flag::Zero, v == 0);
+        self.write_flag(Flag::Negative, is_negative(v));
+    }
+
+    fn add_with_carry(&mut self, data: u8) {
+        let carry = if self.read_flag(Flag::Carry) { 1 } else { 0 };
+        let sum = self.a as u16 + data as u16 + carry;
+        let result = sum as u8;
+
+        self.write_flag(Flag::Carry, sum > 0xff);
+        self.write_flag(Flag::Overflow, (self.a ^ result) & (data ^ result) & 0b10000000 != 0);
+        self.update_zero_and_negative_flags(result);
+
+        self.a = result;
+    }
+
+    fn shift_left(&mut self, data: u8, carry_in: bool) -> u8 {
+        let result = data << 1 | carry_in as u8;
+        self.write_flag(Flag::Carry, data & 0b10000000 == 0b10000000);
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    fn shift_right(&mut self, data: u8, carry_in: bool) -> u8 {
+        let result = data >> 1 | (carry_in as u8) << 7;
+        self.write_flag(Flag::Carry, data & 0b00000001 == 0b00000001);
+        self.update_zero_and_negative_flags(result);
+        result
+    }
+
+    // Interrupts are polled before the operand fetch, and also before fixing the high byte of PC
+    // when the branch crosses a page. A taken branch doesn't poll before its last cycle otherwise.
+    // https://wiki.nesdev.com/w/index.php/CPU_interrupts#Branch_instructions_and_interrupts
+    fn branch_relative<B: Bus>(&mut self, bus: &mut B, condition: bool) -> usize {
+        let interrupt_poll = self.interrupt_poll;
+        let data = self.fetch_byte(bus) as i8;
 
         if condition {
             let prev_pc = self.pc;
+            self.dummy_read(bus, prev_pc);
             self.pc = (self.pc as i32 + data as i32) as u16;
 
-            if (self.pc as u16 & 0xff00) == (prev_pc & 0xff00) {
+            if (self.pc & 0xff00) == (prev_pc & 0xff00) {
+                self.previous_interrupt_poll = interrupt_poll;
                 1
             } else {
+                self.dummy_read(bus, (prev_pc & 0xff00) | (self.pc & 0x00ff));
+                self.previous_interrupt_poll = self.previous_interrupt_poll.or(interrupt_poll);
                 2
             }
         } else {
@@ -245,383 +565,836 @@ impl Cpu {
         }
     }
 
-    fn execute_instruction(&mut self, nes: &mut Nes) -> usize {
-        let Instruction(opcode, mode, cycle) = self.fetch_byte(nes).into();
+    fn execute_instruction<B: Bus>(&mut self, bus: &mut B) -> usize {
+        let start = self.cycles;
+        let Instruction(opcode, mode, cycle) = self.fetch_byte(bus).into();
 
-        // Debug print
-        match mode {
-            Addressing::Implied => debug!("{:?}", opcode),
-            Addressing::Accumulator => debug!("{:?} A", opcode),
-            Addressing::Immediate => debug!("{:?} #${:02X}", opcode, self.read(nes, self.pc)),
-            Addressing::ZeroPage => debug!("{:?} ${:02X}", opcode, self.read(nes, self.pc)),
-            Addressing::ZeroPageX => debug!("{:?} ${:02X}, X", opcode, self.read(nes, self.pc)),
-            Addressing::ZeroPageY => debug!("{:?} ${:02X}, Y", opcode, self.read(nes, self.pc)),
-            Addressing::Relative => debug!("{:?} ${:02X}", opcode, self.read(nes, self.pc)),
-            Addressing::Absolute => debug!("{:?} ${:02X}{:02X}",
-                                           opcode, self.read(nes, self.pc+1), self.read(nes, self.pc)),
-            Addressing::AbsoluteX => debug!("{:?} ${:02X}{:02X}, X",
-                                            opcode, self.read(nes, self.pc+1), self.read(nes, self.pc)),
-            Addressing::AbsoluteY => debug!("{:?} ${:02X}{:02X}, Y",
-                                            opcode, self.read(nes, self.pc+1), self.read(nes, self.pc)),
-            Addressing::Indirect=> debug!("{:?} (${:02X}{:02X})",
-                                          opcode, self.read(nes, self.pc+1), self.read(nes, self.pc)),
-            Addressing::IndexedIndirect => debug!("{:?} (${:02X}, X)", opcode, self.read(nes, self.pc)),
-            Addressing::IndirectIndexed => debug!("{:?} (${:02X}), Y", opcode, self.read(nes, self.pc)),
-            Addressing::UNKNOWN => {},
+        debug!("{}", disasm::disassemble(self.pc.wrapping_sub(1), |addr| bus.peek(addr)));
+
+        // Instructions without an operand read the next byte in their second cycle anyway.
+        if mode == Addressing::Implied || mode == Addressing::Accumulator {
+            self.dummy_read(bus, self.pc);
         }
 
         let additional_cycle = match opcode {
-            Opcode::ASL => self.instruction_asl(nes, mode),
-            Opcode::BMI => self.instruction_bmi(nes, mode),
-            Opcode::BNE => self.instruction_bne(nes, mode),
-            Opcode::BPL => self.instruction_bpl(nes, mode),
-            Opcode::BRK => self.instruction_brk(nes, mode),
-            Opcode::BVC => self.instruction_bvc(nes, mode),
+            Opcode::ADC => self.instruction_adc(bus, mode),
+            Opcode::AND => self.instruction_and(bus, mode),
+            Opcode::ASL => self.instruction_asl(bus, mode),
+            Opcode::BCC => self.instruction_bcc(bus, mode),
+            Opcode::BCS => self.instruction_bcs(bus, mode),
+            Opcode::BEQ => self.instruction_beq(bus, mode),
+            Opcode::BIT => self.instruction_bit(bus, mode),
+            Opcode::BMI => self.instruction_bmi(bus, mode),
+            Opcode::BNE => self.instruction_bne(bus, mode),
+            Opcode::BPL => self.instruction_bpl(bus, mode),
+            Opcode::BRK => self.instruction_brk(bus, mode),
+            Opcode::BVC => self.instruction_bvc(bus, mode),
+            Opcode::BVS => self.instruction_bvs(bus, mode),
             Opcode::CLC => self.instruction_clear_flag(Flag::Carry),
             Opcode::CLD => self.instruction_clear_flag(Flag::Decimal),
-            Opcode::CLI => self.instruction_clear_flag(Flag::InterruptDisable),
+            Opcode::CLI => self.instruction_cli(bus, mode),
             Opcode::CLV => self.instruction_clear_flag(Flag::Overflow),
-            Opcode::CMP => self.instruction_compare(nes, mode, self.a),
-            Opcode::DEC => self.instruction_dec(nes, mode),
-            Opcode::DEY => self.instruction_dey(nes, mode),
-            Opcode::INX => self.instruction_inx(nes, mode),
-            Opcode::ISC => self.instruction_isc(nes, mode),
-            Opcode::JMP => self.instruction_jmp(nes, mode),
-            Opcode::JSR => self.instruction_jsr(nes, mode),
-            Opcode::LDA => self.instruction_lda(nes, mode),
-            Opcode::LDX => self.instruction_ldx(nes, mode),
-            Opcode::LDY => self.instruction_ldy(nes, mode),
-            Opcode::NOP => self.instruction_nop(nes, mode),
-            Opcode::SEI => self.instruction_sei(nes, mode),
-            Opcode::STA => self.instruction_sta(nes, mode),
-            Opcode::TXS => self.instruction_txs(nes, mode),
+            Opcode::CMP => self.instruction_compare(bus, mode, self.a),
+            Opcode::CPX => self.instruction_compare(bus, mode, self.x),
+            Opcode::CPY => self.instruction_compare(bus, mode, self.y),
+            Opcode::DEC => self.instruction_dec(bus, mode),
+            Opcode::DEX => self.instruction_dex(bus, mode),
+            Opcode::DEY => self.instruction_dey(bus, mode),
+            Opcode::EOR => self.instruction_eor(bus, mode),
+            Opcode::INC => self.instruction_inc(bus, mode),
+            Opcode::INX => self.instruction_inx(bus, mode),
+            Opcode::INY => self.instruction_iny(bus, mode),
+            Opcode::ISC => self.instruction_isc(bus, mode),
+            Opcode::JMP => self.instruction_jmp(bus, mode),
+            Opcode::JSR => self.instruction_jsr(bus, mode),
+            Opcode::LDA => self.instruction_lda(bus, mode),
+            Opcode::LDX => self.instruction_ldx(bus, mode),
+            Opcode::LDY => self.instruction_ldy(bus, mode),
+            Opcode::LSR => self.instruction_lsr(bus, mode),
+            Opcode::NOP => self.instruction_nop(bus, mode),
+            Opcode::ORA => self.instruction_ora(bus, mode),
+            Opcode::PHA => self.instruction_pha(bus, mode),
+            Opcode::PHP => self.instruction_php(bus, mode),
+            Opcode::PLA => self.instruction_pla(bus, mode),
+            Opcode::PLP => self.instruction_plp(bus, mode),
+            Opcode::ROL => self.instruction_rol(bus, mode),
+            Opcode::ROR => self.instruction_ror(bus, mode),
+            Opcode::RTI => self.instruction_rti(bus, mode),
+            Opcode::RTS => self.instruction_rts(bus, mode),
+            Opcode::SBC => self.instruction_sbc(bus, mode),
+            Opcode::SEC => self.instruction_set_flag(Flag::Carry),
+            Opcode::SED => self.instruction_set_flag(Flag::Decimal),
+            Opcode::SEI => self.instruction_sei(bus, mode),
+            Opcode::STA => self.instruction_sta(bus, mode),
+            Opcode::STX => self.instruction_stx(bus, mode),
+            Opcode::STY => self.instruction_sty(bus, mode),
+            Opcode::TAX => self.instruction_tax(bus, mode),
+            Opcode::TAY => self.instruction_tay(bus, mode),
+            Opcode::TSX => self.instruction_tsx(bus, mode),
+            Opcode::TXA => self.instruction_txa(bus, mode),
+            Opcode::TXS => self.instruction_txs(bus, mode),
+            Opcode::TYA => self.instruction_tya(bus, mode),
 
             // Unofficial instructions
-            Opcode::KIL => self.instruction_kil(nes, mode),
-            Opcode::SLO => self.instruction_slo(nes, mode),
-
-            _ => {
-                self.dump();
-                panic!("unknown opcode `{}` at 0x{:X}", opcode, self.pc-1)
-            }
+            Opcode::ALR => self.instruction_alr(bus, mode),
+            Opcode::ANC => self.instruction_anc(bus, mode),
+            Opcode::ARR => self.instruction_arr(bus, mode),
+            Opcode::AXS => self.instruction_axs(bus, mode),
+            Opcode::DCP => self.instruction_dcp(bus, mode),
+            Opcode::KIL => self.instruction_kil(bus, mode),
+            Opcode::LAS => self.instruction_las(bus, mode),
+            Opcode::LAX => self.instruction_lax(bus, mode),
+            Opcode::RLA => self.instruction_rla(bus, mode),
+            Opcode::RRA => self.instruction_rra(bus, mode),
+            Opcode::SAX => self.instruction_sax(bus, mode),
+            Opcode::SHA => self.instruction_sha(bus, mode),
+            Opcode::SHX => self.instruction_shx(bus, mode),
+            Opcode::SHY => self.instruction_shy(bus, mode),
+            Opcode::SLO => self.instruction_slo(bus, mode),
+            Opcode::SRE => self.instruction_sre(bus, mode),
+            Opcode::TAS => self.instruction_tas(bus, mode),
+            Opcode::XAA => self.instruction_xaa(bus, mode),
         };
 
+        debug_assert_eq!(self.cycles - start, cycle + additional_cycle,
+                         "{:?} {:?} took a wrong number of bus accesses", opcode, mode);
+
         cycle + additional_cycle
     }
 
+    fn instruction_cli<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.write_flag(Flag::InterruptDisable, false);
+
+        0
+    }
+
     fn instruction_clear_flag(&mut self, flag: Flag) -> usize {
         self.write_flag(flag, false);
         0
     }
 
-    fn instruction_compare(&mut self, nes: &mut Nes, mode: Addressing, val: u8) -> usize {
-        let (_, data, page_crossed) = self.fetch_addressed_data(nes, &mode);
+    fn instruction_set_flag(&mut self, flag: Flag) -> usize {
+        self.write_flag(flag, true);
+        0
+    }
+
+    fn instruction_compare<B: Bus>(&mut self, bus: &mut B, mode: Addressing, val: u8) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
 
         self.write_flag(Flag::Carry, val >= data);
         self.write_flag(Flag::Zero, val == data);
         self.write_flag(Flag::Negative, is_negative(val.wrapping_sub(data)));
 
-        if page_crossed {
-            match mode {
-                Addressing::AbsoluteX => 1,
-                Addressing::AbsoluteY => 1,
-                Addressing::IndirectIndexed => 1,
-                _ => 0,
-            }
-        } else {
-            0
-        }
+        page_crossing_cycle(&mode, page_crossed)
     }
 
-    fn instruction_asl(&mut self, nes: &mut Nes, mode: Addressing) -> usize {
-        let (addr, data, _) = self.fetch_addressed_data(nes, &mode);
+    fn instruction_adc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.add_with_carry(data);
+
+        page_crossing_cycle(&mode, page_crossed)
+    }
 
-        let next = data.wrapping_shl(1);
+    // AND + LSR
+    fn instruction_alr<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+        self.a = self.shift_right(self.a & data, false);
 
-        self.write_flag(Flag::Carry, data & 0b10000000 == 0b10000000);
-        self.write_flag(Flag::Zero, self.a == 0);
-        self.write_flag(Flag::Negative, is_negative(next));
+        0
+    }
+
+    // AND, then C is copied from N
+    fn instruction_anc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+        self.a &= data;
+        self.update_zero_and_negative_flags(self.a);
+        self.write_flag(Flag::Carry, is_negative(self.a));
+
+        0
+    }
+
+    fn instruction_and<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.a &= data;
+        self.update_zero_and_negative_flags(self.a);
+
+        page_crossing_cycle(&mode, page_crossed)
+    }
+
+    // AND + ROR, but C and V come from bit 6 and bit 6 xor bit 5 of the result
+    fn instruction_arr<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+        let carry = self.read_flag(Flag::Carry) as u8;
+        self.a = (self.a & data) >> 1 | carry << 7;
 
-        if let Some(addr) = addr {
-            self.write(nes, addr, next)
-        } else { // Addressing mode is Accumulator
-            self.a = next;
+        self.update_zero_and_negative_flags(self.a);
+        self.write_flag(Flag::Carry, self.a & 0b01000000 != 0);
+        self.write_flag(Flag::Overflow, ((self.a >> 6) ^ (self.a >> 5)) & 1 != 0);
+
+        0
+    }
+
+    fn instruction_asl<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| cpu.shift_left(data, false));
+
+        0
+    }
+
+    // X = (A & X) - operand, without borrow. Flags are set like CMP.
+    fn instruction_axs<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+        let val = self.a & self.x;
+        self.x = val.wrapping_sub(data);
+
+        self.write_flag(Flag::Carry, val >= data);
+        self.update_zero_and_negative_flags(self.x);
+
+        0
+    }
+
+    fn instruction_bcc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        if mode != Addressing::Relative {
+            panic!("Invalid BCC addressing mode: {:?}", mode);
+        }
+
+        self.branch_relative(bus, !self.read_flag(Flag::Carry))
+    }
+
+    fn instruction_bcs<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        if mode != Addressing::Relative {
+            panic!("Invalid BCS addressing mode: {:?}", mode);
+        }
+
+        self.branch_relative(bus, self.read_flag(Flag::Carry))
+    }
+
+    fn instruction_beq<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        if mode != Addressing::Relative {
+            panic!("Invalid BEQ addressing mode: {:?}", mode);
         }
 
+        self.branch_relative(bus, self.read_flag(Flag::Zero))
+    }
+
+    fn instruction_bit<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+
+        self.write_flag(Flag::Zero, self.a & data == 0);
+        self.write_flag(Flag::Overflow, data & 0b01000000 == 0b01000000);
+        self.write_flag(Flag::Negative, is_negative(data));
+
         0
     }
 
-    fn instruction_bmi(&mut self, nes: &mut Nes, mode: Addressing) -> usize {
+    fn instruction_bmi<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
         if mode != Addressing::Relative {
             panic!("Invalid BMI addressing mode: {:?}", mode);
         }
 
-        self.branch_relative(nes, self.read_flag(Flag::Negative))
+        self.branch_relative(bus, self.read_flag(Flag::Negative))
     }
 
-    fn instruction_bne(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
+    fn instruction_bne<B: Bus>(&mut self, bus: &mut B, addressing: Addressing) -> usize {
         if addressing != Addressing::Relative {
             panic!("Unknown BNE addressing mode: {:?}", addressing);
         }
 
-        self.branch_relative(nes, !self.read_flag(Flag::Zero))
+        self.branch_relative(bus, !self.read_flag(Flag::Zero))
     }
 
-    fn instruction_bpl(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
+    fn instruction_bpl<B: Bus>(&mut self, bus: &mut B, addressing: Addressing) -> usize {
         if addressing != Addressing::Relative {
             panic!("Invalid BPL addressing mode: {:?}", addressing);
         }
 
-        self.branch_relative(nes, !self.read_flag(Flag::Negative))
+        self.branch_relative(bus, !self.read_flag(Flag::Negative))
     }
 
-    fn instruction_brk(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
+    fn instruction_brk<B: Bus>(&mut self, bus: &mut B, addressing: Addressing) -> usize {
         if addressing != Addressing::Implied {
             panic!("Unknown BRK addressing mode: {:?}", addressing);
         }
 
-        nes.cpu_interruption = Interruption::BRK;
-        self.write_flag(Flag::Break, true);
+        // BRK has a padding byte after the opcode, which was read in the second cycle.
+        // The return address skips it.
+        self.pc = self.pc.wrapping_add(1);
+        self.interrupt(bus, Interruption::BRK);
 
         0
     }
 
-    fn instruction_bvc(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
+    fn instruction_bvc<B: Bus>(&mut self, bus: &mut B, addressing: Addressing) -> usize {
         if addressing != Addressing::Relative {
             panic!("Unknown BVC addressing mode: {:?}", addressing);
         }
 
-        let val = self.fetch_byte(nes) as i8;
-        let mut additional_cycle = 0;
-
-        if !self.read_flag(Flag::Overflow) {
-            let addr = self.pc as i32 + val as i32;
-            if (addr as u16 & 0xff00) != (self.pc & 0xff00) {
-                additional_cycle += 1;
-            }
+        self.branch_relative(bus, !self.read_flag(Flag::Overflow))
+    }
 
-            self.pc = addr as u16;
-            additional_cycle += 1;
+    fn instruction_bvs<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        if mode != Addressing::Relative {
+            panic!("Invalid BVS addressing mode: {:?}", mode);
         }
 
-        additional_cycle
+        self.branch_relative(bus, self.read_flag(Flag::Overflow))
     }
 
-    fn instruction_dec(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let addr = match addressing {
-            Addressing::ZeroPage => self.fetch_byte(nes) as u16,
-            Addressing::ZeroPageX => self.fetch_byte(nes).wrapping_add(self.x) as u16,
-            _ => panic!("Unknown DEC addressing mode: {:?}", addressing),
-        };
-        let val = self.read(nes, addr);
-        let data = val.wrapping_add(!1+1);
-        self.write(nes, addr, data);
-        self.write_flag(Flag::Zero, data == 0);
-        self.write_flag(Flag::Negative, is_negative(data));
+    // DEC + CMP
+    fn instruction_dcp<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |_, data| data.wrapping_sub(1));
+
+        self.write_flag(Flag::Carry, self.a >= data);
+        self.update_zero_and_negative_flags(self.a.wrapping_sub(data));
 
         0
     }
 
-    fn instruction_dey(&mut self, _: &mut Nes, _: Addressing) -> usize {
-        self.y = self.y.wrapping_add(!1+1);
-        self.write_flag(Flag::Zero, self.y == 0);
-        self.write_flag(Flag::Negative, is_negative(self.y));
+    fn instruction_dec<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| {
+            let result = data.wrapping_sub(1);
+            cpu.update_zero_and_negative_flags(result);
+            result
+        });
 
         0
     }
 
-    fn instruction_inx(&mut self, _: &mut Nes, _: Addressing) -> usize {
-        self.x = self.x.wrapping_add(1);
-        self.write_flag(Flag::Zero, self.x == 0);
-        self.write_flag(Flag::Negative, is_negative(self.x));
+    fn instruction_dex<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.x = self.x.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.x);
 
         0
     }
 
-    // ISC = INC + SBC
-    fn instruction_isc(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let addr = match addressing {
-            Addressing::AbsoluteX => self.fetch_word(nes) + self.x as u16,
-            _ => panic!("Unknown ISC addressing mode: {:?}", addressing),
-        };
+    fn instruction_dey<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.y = self.y.wrapping_sub(1);
+        self.update_zero_and_negative_flags(self.y);
 
-        // INC
-        let data = self.read(nes, addr);
-        let incremented_val = data.wrapping_add(1);
-        self.write(nes, addr, incremented_val);
+        0
+    }
 
-        // SBC
-        let c = if self.read_flag(Flag::Carry) { 0 } else { 1 };
-        let (next_val, overflowed1) = self.a.overflowing_sub(incremented_val);
-        let (result, overflowed2) = next_val.overflowing_sub(c);
+    fn instruction_eor<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.a ^= data;
+        self.update_zero_and_negative_flags(self.a);
 
-        self.write_flag(Flag::Carry, !(is_carried(self.a, incremented_val) || is_carried(next_val, c)));
-        self.write_flag(Flag::Zero, result == 0);
-        self.write_flag(Flag::Overflow, overflowed1 || overflowed2);
-        self.write_flag(Flag::Negative, is_negative(result));
+        page_crossing_cycle(&mode, page_crossed)
+    }
 
-        self.a = result;
+    fn instruction_inc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| {
+            let result = data.wrapping_add(1);
+            cpu.update_zero_and_negative_flags(result);
+            result
+        });
 
         0
     }
 
-    fn instruction_jmp(&mut self, nes: &mut Nes, _: Addressing) -> usize {
-        let addr = self.fetch_word(nes);
-        self.pc = addr;
+    fn instruction_inx<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.x = self.x.wrapping_add(1);
+        self.update_zero_and_negative_flags(self.x);
+
+        0
+    }
+
+    fn instruction_iny<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.y = self.y.wrapping_add(1);
+        self.update_zero_and_negative_flags(self.y);
+
+        0
+    }
+
+    // ISC = INC + SBC
+    fn instruction_isc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |_, data| data.wrapping_add(1));
+        self.add_with_carry(!data);
 
         0
     }
 
-    fn instruction_jsr(&mut self, nes: &mut Nes, _: Addressing) -> usize {
-        let addr = self.fetch_word(nes);
-        self.push_word(self.pc);
+    fn instruction_jmp<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (addr, _) = self.fetch_operand_address(bus, &mode, false);
         self.pc = addr;
 
         0
     }
 
-    fn instruction_kil(&mut self, _: &mut Nes, mode: Addressing) -> usize {
+    fn instruction_jsr<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        let l = self.fetch_byte(bus) as u16;
+        self.dummy_read_stack(bus);
+        // JSR pushes the address of its last byte before fetching it. RTS adds 1 to it.
+        self.push_word(bus, self.pc);
+        let h = self.fetch_byte(bus) as u16;
+        self.pc = h << 8 | l;
+
+        0
+    }
+
+    fn instruction_kil<B: Bus>(&mut self, _: &mut B, mode: Addressing) -> usize {
         if mode != Addressing::Implied {
             panic!("Invalid KIL addressing mode: {:?}", mode);
         }
 
+        // Stay on the KIL opcode like the real CPU, which keeps the bus locked up.
+        self.pc = self.pc.wrapping_sub(1);
+        self.halted = true;
+        error!("CPU halted by KIL at 0x{:04X}", self.pc);
+
         0
     }
 
-    fn instruction_lda(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let mut additional_cycle = 0;
-        let operand = match addressing {
-            Addressing::Immediate => self.fetch_byte(nes),
-            Addressing::Absolute => {
-                let addr = self.fetch_word(nes);
-                self.read(nes, addr)
-            },
-            Addressing::AbsoluteX => {
-                let word = self.fetch_word(nes);
-                let addr = word.wrapping_add(self.x as u16);
-                if (word & 0xff00) != (addr & 0xff00) {
-                    additional_cycle += 1;
-                }
-                self.read(nes, addr)
-            }
-            _ => panic!("Unknown LDA addressing mode: {:?}", addressing),
-        };
-        self.a = operand;
-        self.write_flag(Flag::Zero, self.a == 0);
-        self.write_flag(Flag::Negative, is_negative(self.a));
+    // A, X and S = memory & S
+    fn instruction_las<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        let val = data & self.s;
+        self.a = val;
+        self.x = val;
+        self.s = val;
+        self.update_zero_and_negative_flags(val);
 
-        additional_cycle
+        page_crossing_cycle(&mode, page_crossed)
     }
 
-    fn instruction_ldx(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let operand = match addressing {
-            Addressing::Immediate => self.fetch_byte(nes),
-            _ => panic!("Unknown addressing mode: {:?}", addressing),
-        };
-        self.x = operand;
-        self.write_flag(Flag::Zero, self.x == 0);
-        self.write_flag(Flag::Negative, is_negative(self.x));
+    // LDA + LDX
+    fn instruction_lax<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        // The immediate form is unstable; it ORs A with a chip dependent magic constant first.
+        let val = if mode == Addressing::Immediate { (self.a | UNSTABLE_MAGIC) & data } else { data };
+        self.a = val;
+        self.x = val;
+        self.update_zero_and_negative_flags(val);
 
-        0
+        page_crossing_cycle(&mode, page_crossed)
     }
 
-    fn instruction_ldy(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let operand = match addressing {
-            Addressing::Immediate => self.fetch_byte(nes),
-            _ => panic!("Unknown addressing mode: {:?}", addressing),
-        };
-        self.y = operand;
-        self.write_flag(Flag::Zero, self.y == 0);
-        self.write_flag(Flag::Negative, is_negative(self.y));
+    fn instruction_lda<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.a = data;
+        self.update_zero_and_negative_flags(self.a);
 
-        0
+        page_crossing_cycle(&mode, page_crossed)
     }
 
-    fn instruction_nop(&mut self, nes: &mut Nes, mode: Addressing) -> usize {
-        let (_, _, page_crossed) = self.fetch_addressed_data(nes, &mode);
+    fn instruction_ldx<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.x = data;
+        self.update_zero_and_negative_flags(self.x);
 
-        if mode == Addressing::AbsoluteX && page_crossed {
-            1
-        } else {
-            0
-        }
+        page_crossing_cycle(&mode, page_crossed)
     }
 
-    fn instruction_sei(&mut self, _: &mut Nes, _: Addressing) -> usize {
-        self.write_flag(Flag::InterruptDisable, true);
+    fn instruction_ldy<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.y = data;
+        self.update_zero_and_negative_flags(self.y);
+
+        page_crossing_cycle(&mode, page_crossed)
+    }
+
+    fn instruction_lsr<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| cpu.shift_right(data, false));
 
         0
     }
 
-    // ASL + ORA
-    fn instruction_slo(&mut self, nes: &mut Nes, mode: Addressing) -> usize {
-        let (_, data, _) = self.fetch_addressed_data(nes, &mode);
+    fn instruction_nop<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, _, page_crossed) = self.fetch_addressed_data(bus, &mode);
 
-        // ASL
-        let val = data.wrapping_shl(1);
-        self.write_flag(Flag::Carry, data & 0b10000000 == 0b10000000);
+        page_crossing_cycle(&mode, page_crossed)
+    }
+
+    fn instruction_ora<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.a |= data;
+        self.update_zero_and_negative_flags(self.a);
 
-        // ORA
-        self.a = val | self.a;
-        self.write_flag(Flag::Zero, self.a == 0);
-        self.write_flag(Flag::Negative, is_negative(self.a));
+        page_crossing_cycle(&mode, page_crossed)
+    }
+
+    fn instruction_pha<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        self.push_byte(bus, self.a);
 
         0
     }
 
-    fn instruction_sta(&mut self, nes: &mut Nes, addressing: Addressing) -> usize {
-        let addr = match addressing {
-            Addressing::Absolute => self.fetch_word(nes),
-            _ => panic!("Unknown addressing mode: {:?}", addressing),
-        };
-        self.write(nes, addr, self.a);
+    fn instruction_php<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        // PHP always pushes the B flag and the unused bit as 1.
+        self.push_byte(bus, self.status | u8::from(Flag::Break) | u8::from(Flag::Unused));
 
         0
     }
 
-    fn instruction_txs(&mut self, _: &mut Nes, _: Addressing) -> usize {
-        self.s = self.x as u16;
+    fn instruction_pla<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        self.dummy_read_stack(bus);
+        self.a = self.pop(bus);
+        self.update_zero_and_negative_flags(self.a);
 
         0
     }
-}
 
-fn is_negative(v: u8) -> bool {
-    v & 0b10000000 == 0b10000000
-}
+    fn instruction_plp<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        self.dummy_read_stack(bus);
+        let data = self.pop(bus);
+        self.pull_status(data);
 
-fn is_carried(v1: u8, v2: u8) -> bool {
-    let result = v1 as u16 + v2 as u16;
-    result & 0x0100 == 0x0100
-}
+        0
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::RAM_SIZE;
-    use super::PRG_ROM_BASE;
-    use super::Cpu;
-    use super::Flag;
-    use super::Nes;
-    use super::Interruption;
+    // B flag and the unused bit don't exist in P; they are ignored when P is pulled from the stack.
+    fn pull_status(&mut self, data: u8) {
+        let ignored = u8::from(Flag::Break) | u8::from(Flag::Unused);
+        self.status = (data & !ignored) | (self.status & ignored);
+    }
 
-    fn new_test_cpu(prg_rom: Vec<u8>) -> (Cpu, Nes) {
+    // ROL + AND
+    fn instruction_rla<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |cpu, data| {
+            let carry = cpu.read_flag(Flag::Carry);
+            cpu.shift_left(data, carry)
+        });
+        self.a &= data;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+
+    fn instruction_rol<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| {
+            let carry = cpu.read_flag(Flag::Carry);
+            cpu.shift_left(data, carry)
+        });
+
+        0
+    }
+
+    fn instruction_ror<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.modify(bus, &mode, |cpu, data| {
+            let carry = cpu.read_flag(Flag::Carry);
+            cpu.shift_right(data, carry)
+        });
+
+        0
+    }
+
+    // ROR + ADC
+    fn instruction_rra<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |cpu, data| {
+            let carry = cpu.read_flag(Flag::Carry);
+            cpu.shift_right(data, carry)
+        });
+        self.add_with_carry(data);
+
+        0
+    }
+
+    fn instruction_rti<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        self.dummy_read_stack(bus);
+        let data = self.pop(bus);
+        self.pull_status(data);
+        self.pc = self.pop_word(bus);
+
+        0
+    }
+
+    fn instruction_rts<B: Bus>(&mut self, bus: &mut B, _: Addressing) -> usize {
+        self.dummy_read_stack(bus);
+        self.pc = self.pop_word(bus);
+        // The last cycle reads the pulled address while incrementing it.
+        self.dummy_read(bus, self.pc);
+        self.pc = self.pc.wrapping_add(1);
+
+        0
+    }
+
+    fn instruction_sax<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (addr, _) = self.fetch_operand_address(bus, &mode, true);
+        self.write(bus, addr, self.a & self.x);
+
+        0
+    }
+
+    fn instruction_sbc<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, page_crossed) = self.fetch_addressed_data(bus, &mode);
+        self.add_with_carry(!data);
+
+        page_crossing_cycle(&mode, page_crossed)
+    }
+
+    fn instruction_sei<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.write_flag(Flag::InterruptDisable, true);
+
+        0
+    }
+
+    fn instruction_sha<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.store_and_high_byte(bus, &mode, self.a & self.x);
+
+        0
+    }
+
+    fn instruction_shx<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.store_and_high_byte(bus, &mode, self.x);
+
+        0
+    }
+
+    fn instruction_shy<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.store_and_high_byte(bus, &mode, self.y);
+
+        0
+    }
+
+    // SHA, SHX, SHY and TAS store `val & (high byte of the base address + 1)`.
+    // When indexing crosses a page, the stored value also replaces the high byte of the address.
+    fn store_and_high_byte<B: Bus>(&mut self, bus: &mut B, mode: &Addressing, val: u8) {
+        let (addr, page_crossed) = self.fetch_operand_address(bus, mode, true);
+        let high = if page_crossed { (addr >> 8) as u8 } else { ((addr >> 8) as u8).wrapping_add(1) };
+        let data = val & high;
+        let addr = if page_crossed { (data as u16) << 8 | (addr & 0x00ff) } else { addr };
+
+        self.write(bus, addr, data);
+    }
+
+    // ASL + ORA
+    fn instruction_slo<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |cpu, data| cpu.shift_left(data, false));
+        self.a |= data;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+
+    // LSR + EOR
+    fn instruction_sre<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let data = self.modify(bus, &mode, |cpu, data| cpu.shift_right(data, false));
+        self.a ^= data;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+
+    fn instruction_sta<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (addr, _) = self.fetch_operand_address(bus, &mode, true);
+        self.write(bus, addr, self.a);
+
+        0
+    }
+
+    fn instruction_stx<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (addr, _) = self.fetch_operand_address(bus, &mode, true);
+        self.write(bus, addr, self.x);
+
+        0
+    }
+
+    fn instruction_sty<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (addr, _) = self.fetch_operand_address(bus, &mode, true);
+        self.write(bus, addr, self.y);
+
+        0
+    }
+
+    // S = A & X, then SHA-like store of S
+    fn instruction_tas<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        self.s = self.a & self.x;
+        self.store_and_high_byte(bus, &mode, self.s);
+
+        0
+    }
+
+    fn instruction_tax<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.x = self.a;
+        self.update_zero_and_negative_flags(self.x);
+
+        0
+    }
+
+    fn instruction_tay<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.y = self.a;
+        self.update_zero_and_negative_flags(self.y);
+
+        0
+    }
+
+    fn instruction_tsx<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.x = self.s;
+        self.update_zero_and_negative_flags(self.x);
+
+        0
+    }
+
+    fn instruction_txa<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.a = self.x;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+
+    fn instruction_txs<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.s = self.x;
+
+        0
+    }
+
+    fn instruction_tya<B: Bus>(&mut self, _: &mut B, _: Addressing) -> usize {
+        self.a = self.y;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+
+    // A = (A | magic) & X & operand. Unstable on real hardware.
+    fn instruction_xaa<B: Bus>(&mut self, bus: &mut B, mode: Addressing) -> usize {
+        let (_, data, _) = self.fetch_addressed_data(bus, &mode);
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & data;
+        self.update_zero_and_negative_flags(self.a);
+
+        0
+    }
+}
+
+// Indexed reads take one more cycle when the effective address crosses a page boundary.
+fn page_crossing_cycle(mode: &Addressing, page_crossed: bool) -> usize {
+    match mode {
+        Addressing::AbsoluteX | Addressing::AbsoluteY | Addressing::IndirectIndexed if page_crossed => 1,
+        _ => 0,
+    }
+}
+
+fn is_negative(v: u8) -> bool {
+    v & 0b10000000 == 0b10000000
+}
+
+#[cfg(test)]
+// Flags are compared with assert_eq! like the registers.
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::STACK_BASE;
+    use super::Cpu;
+    use super::Flag;
+    use super::IrqSource;
+    use crate::bus::Access;
+    use crate::bus::Bus;
+    use crate::bus::FlatMemory;
+    use crate::bus::LoggingBus;
+    use crate::nes::Nes;
+    use crate::nes::PRG_ROM_BASE;
+
+    fn new_test_cpu(prg_rom: Vec<u8>) -> (Cpu, Nes) {
         (
             Cpu {
                 a: 0,
                 x: 0,
                 y: 0,
                 pc: PRG_ROM_BASE,
-                s: 0x00fd,
+                s: 0xfd,
                 status: 0,
-                ram: [0; RAM_SIZE],
+                halted: false,
+                cycles: 0,
+                cycle_stepped: false,
+                nmi_line: false,
+                nmi_pending: false,
+                interrupt_poll: None,
+                previous_interrupt_poll: None,
             },
             Nes::new_for_test(prg_rom)
         )
     }
 
+    // 16 KiB PRG ROM with `program` at the beginning and the interrupt vectors at the end.
+    // The rest is filled with NOP.
+    fn new_test_rom_with_vectors(program: Vec<u8>, nmi: u16, reset: u16, irq: u16) -> Vec<u8> {
+        let mut rom = program;
+        rom.resize(0x4000, 0xEA);
+        for (offset, vector) in [(0x3FFA, nmi), (0x3FFC, reset), (0x3FFE, irq)] {
+            rom[offset] = (vector & 0x00ff) as u8;
+            rom[offset + 1] = (vector >> 8) as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn bus_accesses() {
+        // Every cycle of every instruction is one bus access, with and without page crossing.
+        for opcode in 0..=0xFF {
+            for index in [0x00, 0xFF] {
+                let (mut cpu, mut nes) = new_test_cpu(vec![opcode, 0x10, 0x00]);
+                cpu.x = index;
+                cpu.y = index;
+                cpu.write(&mut nes, 0x0010, 0x01);
+
+                let start = cpu.cycles;
+                let cycle = cpu.execute_instruction(&mut nes);
+                assert_eq!(cpu.cycles - start, cycle, "opcode {:02X} with index {:02X}", opcode, index);
+            }
+        }
+    }
+
+    #[test]
+    fn cycle_stepped() {
+        // The PPU runs 3 dots per CPU cycle whether it is clocked on every access or after each instruction.
+        for cycle_stepped in [false, true] {
+            let (mut cpu, mut nes) = new_test_cpu(vec![0xBD, 0xF0, 0x00, 0xFE, 0x10, 0x00]);
+            cpu.set_cycle_stepped(cycle_stepped);
+            cpu.x = 0x20;
+            assert_eq!(cpu.tick(&mut nes), 5);
+            assert_eq!(nes.ppu.dot(), 15);
+            assert_eq!(cpu.tick(&mut nes), 7);
+            assert_eq!(nes.ppu.dot(), 36);
+        }
+    }
+
+    #[test]
+    fn dummy_accesses() {
+        let mut memory = FlatMemory::new();
+        // INC $10; LDA $12F0,X; STA $1200,X
+        memory.load(0x0200, &[0xE6, 0x10, 0xBD, 0xF0, 0x12, 0x9D, 0x00, 0x12]);
+        memory.write(0x0010, 0x7F);
+        let mut bus = LoggingBus::new(memory);
+        let mut cpu = Cpu::new();
+        cpu.pc = 0x0200;
+        cpu.x = 0x20;
+
+        // Read-modify-write instructions write the unmodified value back first
+        assert_eq!(cpu.execute_instruction(&mut bus), 5);
+        assert_eq!(bus.log, vec![
+            Access::Read(0x0200, 0xE6),
+            Access::Read(0x0201, 0x10),
+            Access::Read(0x0010, 0x7F),
+            Access::Write(0x0010, 0x7F),
+            Access::Write(0x0010, 0x80),
+        ]);
+
+        // Indexed loads read the wrong page before the carry is fixed
+        bus.log.clear();
+        assert_eq!(cpu.execute_instruction(&mut bus), 5);
+        assert_eq!(bus.log[3..], [Access::Read(0x1210, 0x00), Access::Read(0x1310, 0x00)]);
+
+        // Indexed stores always read the address first, even without page crossing
+        bus.log.clear();
+        assert_eq!(cpu.execute_instruction(&mut bus), 5);
+        assert_eq!(bus.log[3..], [Access::Read(0x1220, 0x00), Access::Write(0x1220, 0x00)]);
+    }
+
+    #[test]
+    fn flat_memory() {
+        let mut memory = FlatMemory::new();
+        // LDA #$42; STA $C000
+        memory.load(0x0200, &[0xA9, 0x42, 0x8D, 0x00, 0xC0]);
+        memory.load(0xFFFC, &[0x00, 0x02]);
+        let mut cpu = Cpu::new();
+
+        assert_eq!(cpu.power_on(&mut memory), 7);
+        assert_eq!(cpu.pc, 0x0200);
+        assert_eq!(cpu.tick(&mut memory), 2);
+        assert_eq!(cpu.tick(&mut memory), 4);
+        assert_eq!(memory.peek(0xC000), 0x42);
+    }
+
     #[test]
     fn instruction_clear_flag() { // CLC, CLD, CLV
-        for case in vec![
+        for case in [
             (Flag::Carry, 0x18),
             (Flag::Decimal, 0xD8),
             (Flag::InterruptDisable, 0x58),
@@ -634,6 +1407,118 @@ mod tests {
         }
     }
 
+    #[test]
+    fn instruction_adc() {
+        // Immediate; Flag behavior
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x69, 0x02]);
+        cpu.a = 0x03;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x05);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Overflow), false);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x69, 0x01]);
+        cpu.a = 0xFF;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x01);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x69, 0x50]);
+        cpu.a = 0x50;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0xA0);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Overflow), true);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x69, 0x90]);
+        cpu.a = 0xD0;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x60);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Overflow), true);
+
+        // AbsoluteY (page crossed)
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x79, 0xFF, 0x00]);
+        cpu.a = 0x01;
+        cpu.y = 0x02;
+        cpu.write(&mut nes, 0x0101, 0x02);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.a, 0x03);
+    }
+
+    #[test]
+    fn instruction_alr() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x4B, 0b00000011]);
+        cpu.a = 0b10000001;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_anc() {
+        for opcode in [0x0B, 0x2B] {
+            let (mut cpu, mut nes) = new_test_cpu(vec![opcode, 0b10000001]);
+            cpu.a = 0b11110000;
+            assert_eq!(cpu.execute_instruction(&mut nes), 2);
+            assert_eq!(cpu.a, 0b10000000);
+            assert_eq!(cpu.read_flag(Flag::Carry), true);
+            assert_eq!(cpu.read_flag(Flag::Negative), true);
+        }
+    }
+
+    #[test]
+    fn instruction_and() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x29, 0b00001111]);
+        cpu.a = 0b10101010;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b00001010);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), false);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x25, 0x10]);
+        cpu.a = 0b10101010;
+        cpu.write(&mut nes, 0x0010, 0b01010101);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        // IndirectIndexed (page crossed)
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x31, 0x10]);
+        cpu.a = 0b11110000;
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0010, 0xFF);
+        cpu.write(&mut nes, 0x0011, 0x00);
+        cpu.write(&mut nes, 0x0100, 0b10010000);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.a, 0b10010000);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_arr() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6B, 0b11000000]);
+        cpu.a = 0b11111111;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b11100000);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Overflow), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6B, 0b10000000]);
+        cpu.a = 0b11111111;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b01000000);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Overflow), true);
+    }
+
     #[test]
     fn instruction_asl() {
         // Accumulator
@@ -648,7 +1533,7 @@ mod tests {
         assert_eq!(cpu.execute_instruction(&mut nes), 5);
         assert_eq!(cpu.read(&mut nes, 0x10), 4);
         assert_eq!(cpu.read_flag(Flag::Carry), false);
-        assert_eq!(cpu.read_flag(Flag::Zero), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
         assert_eq!(cpu.read_flag(Flag::Negative), false);
 
         let (mut cpu, mut nes) = new_test_cpu(vec![0x06, 0x10]);
@@ -664,7 +1549,7 @@ mod tests {
         assert_eq!(cpu.execute_instruction(&mut nes), 5);
         assert_eq!(cpu.read(&mut nes, 0x10), 0b10000000);
         assert_eq!(cpu.read_flag(Flag::Carry), false);
-        assert_eq!(cpu.read_flag(Flag::Zero), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
         assert_eq!(cpu.read_flag(Flag::Negative), true);
 
         // ZeroPageX
@@ -688,6 +1573,86 @@ mod tests {
         assert_eq!(cpu.read(&mut nes, 0x0112), 6);
     }
 
+    #[test]
+    fn instruction_axs() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xCB, 0x02]);
+        cpu.a = 0b00001111;
+        cpu.x = 0b00000111;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0x05);
+        assert_eq!(cpu.a, 0b00001111);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xCB, 0x02]);
+        cpu.a = 0x01;
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0xFF);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_bcc() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x90, 0x03]);
+        cpu.write_flag(Flag::Carry, false);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2 + 0x03);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x90, 0x03]);
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2);
+    }
+
+    #[test]
+    fn instruction_bcs() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB0, 0x03]);
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2 + 0x03);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB0, 0x03]);
+        cpu.write_flag(Flag::Carry, false);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2);
+    }
+
+    #[test]
+    fn instruction_beq() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xF0, 0x03]);
+        cpu.write_flag(Flag::Zero, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2 + 0x03);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xF0, 0x03]);
+        cpu.write_flag(Flag::Zero, false);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2);
+    }
+
+    #[test]
+    fn instruction_bit() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x24, 0x10]);
+        cpu.a = 0b00000001;
+        cpu.write(&mut nes, 0x0010, 0b11000000);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+        assert_eq!(cpu.read_flag(Flag::Overflow), true);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+        assert_eq!(cpu.a, 0b00000001);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x2C, 0x10, 0x01]);
+        cpu.a = 0b00000001;
+        cpu.write(&mut nes, 0x0110, 0b00000001);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Overflow), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), false);
+    }
+
     #[test]
     fn instruction_bmi() { // Branch if Minus
         let (mut cpu, mut nes) = new_test_cpu(vec![0x30, 0x03]);
@@ -744,10 +1709,22 @@ mod tests {
 
     #[test]
     fn instruction_brk() {
-        let (mut cpu, mut nes) = new_test_cpu(vec![0x00]);
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x00, 0xFF], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.pc, 0xA000);
+        assert_eq!(cpu.read_flag(Flag::InterruptDisable), true);
+        // The return address skips the padding byte, and B is set only in the pushed P
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 3), 0x80);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 2), 0x02);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00110001);
+        assert_eq!(cpu.read_flag(Flag::Break), false);
+
+        // BRK isn't masked by I
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x00, 0xFF], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
         assert_eq!(cpu.execute_instruction(&mut nes), 7);
-        assert_eq!(nes.cpu_interruption, Interruption::BRK);
-        assert_eq!(cpu.read_flag(Flag::Break), true);
+        assert_eq!(cpu.pc, 0xA000);
     }
 
     #[test]
@@ -769,10 +1746,23 @@ mod tests {
     }
 
     #[test]
-    fn instruction_cmp() {
-        // Immediate, Flag behavior
-        let (mut cpu, mut nes) = new_test_cpu(vec![0xC9, 0x03]);
-        cpu.a = 4;
+    fn instruction_bvs() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x70, 0x03]);
+        cpu.write_flag(Flag::Overflow, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2 + 0x03);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x70, 0x03]);
+        cpu.write_flag(Flag::Overflow, false);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 2);
+    }
+
+    #[test]
+    fn instruction_cmp() {
+        // Immediate, Flag behavior
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC9, 0x03]);
+        cpu.a = 4;
         assert_eq!(cpu.execute_instruction(&mut nes), 2);
         assert_eq!(cpu.read_flag(Flag::Carry), true);
         assert_eq!(cpu.read_flag(Flag::Zero), false);
@@ -864,6 +1854,66 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::Carry), true);
     }
 
+    #[test]
+    fn instruction_cpx() {
+        // Immediate
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE0, 0x03]);
+        cpu.x = 2;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xEC, 0x10, 0x01]);
+        cpu.x = 3;
+        cpu.write(&mut nes, 0x0110, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_cpy() {
+        // Immediate
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC0, 0x03]);
+        cpu.y = 4;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC4, 0x10]);
+        cpu.y = 3;
+        cpu.write(&mut nes, 0x0010, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_dcp() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC7, 0x10]);
+        cpu.a = 0x02;
+        cpu.write(&mut nes, 0x0010, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0x02);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        // IndirectIndexed: no page crossing penalty
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xD3, 0x10]);
+        cpu.a = 0x01;
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0010, 0xFF);
+        cpu.write(&mut nes, 0x0011, 0x00);
+        cpu.write(&mut nes, 0x0100, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 8);
+        assert_eq!(cpu.read(&mut nes, 0x0100), 0x02);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
     #[test]
     fn instruction_dec() {
         // ZeroPage; Flag behavior
@@ -896,6 +1946,34 @@ mod tests {
         assert_eq!(cpu.read(&mut nes, 0x0011), 0x02);
         assert_eq!(cpu.read_flag(Flag::Zero), false);
         assert_eq!(cpu.read_flag(Flag::Negative), false);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xCE, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0110, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 0x02);
+
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xDE, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0111, 0x03);
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0x02);
+    }
+
+    #[test]
+    fn instruction_dex() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xCA]);
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xCA]);
+        cpu.x = 0x00;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0xFF);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
     }
 
     #[test]
@@ -922,6 +2000,57 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::Negative), true);
     }
 
+    #[test]
+    fn instruction_eor() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x49, 0b11111111]);
+        cpu.a = 0b00001111;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b11110000);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // IndexedIndirect
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x41, 0x10]);
+        cpu.a = 0b00001111;
+        cpu.x = 0x02;
+        cpu.write(&mut nes, 0x0012, 0x20);
+        cpu.write(&mut nes, 0x0013, 0x01);
+        cpu.write(&mut nes, 0x0120, 0b00001111);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_inc() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE6, 0x10]);
+        cpu.write(&mut nes, 0x0010, 0xFF);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        // ZeroPageX wraps around within the zero page
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xF6, 0xFF]);
+        cpu.x = 0x02;
+        cpu.write(&mut nes, 0x0001, 0x7F);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0001), 0x80);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xEE, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0110, 0x01);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 0x02);
+
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xFE, 0x10, 0x01]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0111, 0x01);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0x02);
+    }
+
     #[test]
     fn instruction_inx() {
         let (mut cpu, mut nes) = new_test_cpu(vec![0xE8]);
@@ -946,12 +2075,68 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::Negative), true);
     }
 
+    #[test]
+    fn instruction_iny() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC8]);
+        cpu.y = 0x7F;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.y, 0x80);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xC8]);
+        cpu.y = 0xFF;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.y, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_isc() {
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xFF, 0x10, 0x01]);
+        cpu.a = 0x05;
+        cpu.x = 0x01;
+        cpu.write_flag(Flag::Carry, true);
+        cpu.write(&mut nes, 0x0111, 0x01);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0x02);
+        assert_eq!(cpu.a, 0x03);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE7, 0x10]);
+        cpu.a = 0x00;
+        cpu.write_flag(Flag::Carry, true);
+        cpu.write(&mut nes, 0x0010, 0xFF);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0x00);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+    }
+
     #[test]
     fn instruction_jmp() {
         // Absolute
         let (mut cpu, mut nes) = new_test_cpu(vec![0x4C, 0x03, 0x01]);
         assert_eq!(cpu.execute_instruction(&mut nes), 3);
         assert_eq!(cpu.pc, 0x0103);
+
+        // Indirect
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6C, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0110, 0x03);
+        cpu.write(&mut nes, 0x0111, 0x90);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.pc, 0x9003);
+
+        // Indirect doesn't carry into the high byte of the pointer
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6C, 0xFF, 0x01]);
+        cpu.write(&mut nes, 0x01FF, 0x03);
+        cpu.write(&mut nes, 0x0100, 0x90);
+        cpu.write(&mut nes, 0x0200, 0xA0);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.pc, 0x9003);
     }
 
     #[test]
@@ -959,8 +2144,63 @@ mod tests {
         let (mut cpu, mut nes) = new_test_cpu(vec![0x20, 0x09, 0x90]);
         assert_eq!(cpu.execute_instruction(&mut nes), 6);
         assert_eq!(cpu.pc, 0x9009);
-        assert_eq!(cpu.read(&mut nes, cpu.s+2), (PRG_ROM_BASE >> 8) as u8);
-        assert_eq!(cpu.read(&mut nes, cpu.s+1), (PRG_ROM_BASE & 0x00ff) as u8 + 3);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 2), (PRG_ROM_BASE >> 8) as u8);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), (PRG_ROM_BASE & 0x00ff) as u8 + 2);
+    }
+
+    #[test]
+    fn instruction_kil() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x02, 0xEA]);
+        assert_eq!(cpu.is_halted(), false);
+        cpu.tick(&mut nes);
+        assert_eq!(cpu.is_halted(), true);
+        assert_eq!(cpu.pc, PRG_ROM_BASE);
+
+        assert_eq!(cpu.tick(&mut nes), 0);
+        assert_eq!(cpu.pc, PRG_ROM_BASE);
+    }
+
+    #[test]
+    fn instruction_las() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xBB, 0xFF, 0x00]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0100, 0b10001111);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.a, 0b10001101);
+        assert_eq!(cpu.x, 0b10001101);
+        assert_eq!(cpu.s, 0b10001101);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_lax() {
+        // ZeroPageY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB7, 0x10]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0011, 0x80);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(cpu.x, 0x80);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // AbsoluteY (page crossed)
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xBF, 0xFF, 0x00]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0100, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.a, 0x03);
+        assert_eq!(cpu.x, 0x03);
+
+        // IndexedIndirect
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xA3, 0x10]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0011, 0x20);
+        cpu.write(&mut nes, 0x0012, 0x01);
+        cpu.write(&mut nes, 0x0120, 0x00);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
     }
 
     #[test]
@@ -1004,6 +2244,73 @@ mod tests {
         cpu.x = 0x01;
         assert_eq!(cpu.execute_instruction(&mut nes), 5);
         assert_eq!(cpu.a, 3);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xA5, 0x10]);
+        cpu.write(&mut nes, 0x0010, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.a, 3);
+
+        // ZeroPageX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB5, 0xFF]);
+        cpu.write(&mut nes, 0x0000, 3);
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 3);
+
+        // AbsoluteY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB9, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0111, 3);
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 3);
+
+        // IndexedIndirect; the pointer wraps around within the zero page
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xA1, 0xFE]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x00FF, 0x10);
+        cpu.write(&mut nes, 0x0000, 0x01);
+        cpu.write(&mut nes, 0x0110, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.a, 3);
+
+        // IndirectIndexed
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB1, 0x10]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0010, 0x10);
+        cpu.write(&mut nes, 0x0011, 0x01);
+        cpu.write(&mut nes, 0x0111, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.a, 3);
+    }
+
+    #[test]
+    fn instruction_ldx() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xA6, 0x10]);
+        cpu.write(&mut nes, 0x0010, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.x, 3);
+
+        // ZeroPageY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB6, 0x10]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0011, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.x, 3);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xAE, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0110, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.x, 3);
+
+        // AbsoluteY (page crossed)
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xBE, 0xFF, 0x00]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0100, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.x, 3);
     }
 
     #[test]
@@ -1029,6 +2336,23 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::Negative), true);
     }
 
+    #[test]
+    fn instruction_ldy() {
+        // ZeroPageX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xB4, 0x10]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0011, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.y, 3);
+
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xBC, 0x10, 0x01]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0111, 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.y, 3);
+    }
+
     #[test]
     fn instruction_ldy_immediate() {
         let opcode = 0xa0;
@@ -1052,6 +2376,260 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::Negative), true);
     }
 
+    #[test]
+    fn instruction_lsr() {
+        // Accumulator
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x4A]);
+        cpu.a = 0b00000011;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b00000001);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), false);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x46, 0x10]);
+        cpu.write(&mut nes, 0x0010, 0b00000001);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x5E, 0x10, 0x01]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0111, 0b10000000);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0b01000000);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn instruction_nop() {
+        for (opcode, len, cycle) in [
+            (0xEA, 1, 2),
+            (0x1A, 1, 2),
+            (0x80, 2, 2),
+            (0x04, 2, 3),
+            (0x14, 2, 4),
+            (0x0C, 3, 4),
+            (0x1C, 3, 4),
+        ] {
+            let (mut cpu, mut nes) = new_test_cpu(vec![opcode, 0x10, 0x01]);
+            assert_eq!(cpu.execute_instruction(&mut nes), cycle);
+            assert_eq!(cpu.pc, PRG_ROM_BASE + len);
+        }
+
+        // AbsoluteX (page crossed)
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x1C, 0xFF, 0x00]);
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+    }
+
+    #[test]
+    fn instruction_ora() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x09, 0b10000000]);
+        cpu.a = 0b00000001;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b10000001);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // AbsoluteX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x1D, 0x10, 0x01]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0111, 0);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_pha_pla() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x48, 0xA9, 0x00, 0x68]);
+        cpu.a = 0x80;
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_php_plp() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x08, 0x28]);
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        // B flag and the unused bit are set in the pushed value
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00110001);
+
+        cpu.write(&mut nes, STACK_BASE + cpu.s as u16 + 1, 0b11001110);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.status, 0b11001110);
+    }
+
+    #[test]
+    fn instruction_rla() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x27, 0x10]);
+        cpu.a = 0b00000011;
+        cpu.write_flag(Flag::Carry, true);
+        cpu.write(&mut nes, 0x0010, 0b10000001);
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0b00000011);
+        assert_eq!(cpu.a, 0b00000011);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+    }
+
+    #[test]
+    fn instruction_rol() {
+        // Accumulator
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x2A]);
+        cpu.a = 0b10000000;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b00000001);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+
+        // ZeroPageX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x36, 0x10]);
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0011, 0b01000000);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0011), 0b10000000);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_ror() {
+        // Accumulator
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6A]);
+        cpu.a = 0b00000001;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b10000000);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6E, 0x10, 0x01]);
+        cpu.write(&mut nes, 0x0110, 0b00000001);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 0);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_rra() {
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x6F, 0x10, 0x01]);
+        cpu.a = 0x01;
+        cpu.write(&mut nes, 0x0110, 0b00000101);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 0b00000010);
+        // The carry from ROR is added by ADC
+        assert_eq!(cpu.a, 0x04);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+    }
+
+    #[test]
+    fn instruction_rti() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x40]);
+        cpu.push_word(&mut nes, 0x9003);
+        cpu.push_byte(&mut nes, 0b11111111);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.pc, 0x9003);
+        assert_eq!(cpu.status, 0b11001111);
+    }
+
+    #[test]
+    fn instruction_rts() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x20, 0x04, 0x80, 0x00, 0x60]);
+        let s = cpu.s;
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.pc, 0x8004);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.pc, PRG_ROM_BASE + 3);
+        assert_eq!(cpu.s, s);
+    }
+
+    #[test]
+    fn instruction_sax() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x87, 0x10]);
+        cpu.a = 0b00001111;
+        cpu.x = 0b00111100;
+        cpu.write_flag(Flag::Zero, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0b00001100);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        // ZeroPageY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x97, 0x10]);
+        cpu.a = 0xFF;
+        cpu.x = 0x03;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0011), 0x03);
+    }
+
+    #[test]
+    fn instruction_sbc() {
+        // Immediate; Flag behavior
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE9, 0x02]);
+        cpu.a = 0x05;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x03);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Overflow), false);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE9, 0x02]);
+        cpu.a = 0x05;
+        cpu.write_flag(Flag::Carry, false);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x02);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE9, 0x03]);
+        cpu.a = 0x02;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0xFF);
+        assert_eq!(cpu.read_flag(Flag::Carry), false);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE9, 0x01]);
+        cpu.a = 0x80;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x7F);
+        assert_eq!(cpu.read_flag(Flag::Overflow), true);
+
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xE5, 0x10]);
+        cpu.a = 0x03;
+        cpu.write_flag(Flag::Carry, true);
+        cpu.write(&mut nes, 0x0010, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+    }
+
+    #[test]
+    fn instruction_sbc_unofficial() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xEB, 0x02]);
+        cpu.a = 0x05;
+        cpu.write_flag(Flag::Carry, true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x03);
+    }
+
     #[test]
     fn instruction_sei_implied() {
         let (mut cpu, mut nes) = new_test_cpu(vec![0x78]);
@@ -1059,9 +2637,121 @@ mod tests {
         assert_eq!(cpu.read_flag(Flag::InterruptDisable), true);
     }
 
+    #[test]
+    fn instruction_set_flag() { // SEC, SED, SEI
+        for case in [
+            (Flag::Carry, 0x38),
+            (Flag::Decimal, 0xF8),
+            (Flag::InterruptDisable, 0x78),
+        ] {
+            let (mut cpu, mut nes) = new_test_cpu(vec![case.1]);
+            assert_eq!(cpu.execute_instruction(&mut nes), 2);
+            assert_eq!(cpu.read_flag(case.0), true);
+        }
+    }
+
+    #[test]
+    fn instruction_sha_shx_shy() {
+        // SHA AbsoluteY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x9F, 0x10, 0x01]);
+        cpu.a = 0xFF;
+        cpu.x = 0x0F;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0x02);
+
+        // SHA IndirectIndexed
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x93, 0x10]);
+        cpu.a = 0xFF;
+        cpu.x = 0xFF;
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0010, 0x20);
+        cpu.write(&mut nes, 0x0011, 0x03);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0321), 0x04);
+
+        // SHX AbsoluteY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x9E, 0x10, 0x01]);
+        cpu.x = 0xFF;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0x02);
+
+        // SHY AbsoluteX; the page crossing corrupts the high byte of the address
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x9C, 0xFF, 0x00]);
+        cpu.y = 0x03;
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0100), 0x01);
+    }
+
     #[test]
     fn instruction_slo() {
-        // TODO
+        // IndexedIndirect
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x03, 0x10]);
+        cpu.a = 0b00000001;
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0011, 0x20);
+        cpu.write(&mut nes, 0x0012, 0x01);
+        cpu.write(&mut nes, 0x0120, 0b10000010);
+        assert_eq!(cpu.execute_instruction(&mut nes), 8);
+        assert_eq!(cpu.read(&mut nes, 0x0120), 0b00000100);
+        assert_eq!(cpu.a, 0b00000101);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), false);
+
+        // AbsoluteY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x1B, 0x10, 0x01]);
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0111, 0b01000000);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.a, 0b10000000);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+    }
+
+    #[test]
+    fn instruction_sre() {
+        // ZeroPageX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x57, 0x10]);
+        cpu.a = 0b00000011;
+        cpu.x = 0x01;
+        cpu.write(&mut nes, 0x0011, 0b00000111);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0011), 0b00000011);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.read_flag(Flag::Carry), true);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+    }
+
+    #[test]
+    fn instruction_sta() {
+        // ZeroPage
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x85, 0x10]);
+        cpu.a = 3;
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 3);
+
+        // AbsoluteX: no page crossing penalty
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x9D, 0x10, 0x01]);
+        cpu.a = 3;
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 3);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x99, 0xFF, 0x00]);
+        cpu.a = 3;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.read(&mut nes, 0x0100), 3);
+
+        // IndirectIndexed
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x91, 0x10]);
+        cpu.a = 3;
+        cpu.y = 0x01;
+        cpu.write(&mut nes, 0x0010, 0xFF);
+        cpu.write(&mut nes, 0x0011, 0x01);
+        assert_eq!(cpu.execute_instruction(&mut nes), 6);
+        assert_eq!(cpu.read(&mut nes, 0x0200), 3);
     }
 
     #[test]
@@ -1074,6 +2764,79 @@ mod tests {
         assert_eq!(cpu.read(&mut nes, 0x0111), 3);
     }
 
+    #[test]
+    fn instruction_stx() {
+        // ZeroPageY
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x96, 0x10]);
+        cpu.x = 3;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0011), 3);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x8E, 0x10, 0x01]);
+        cpu.x = 3;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 3);
+    }
+
+    #[test]
+    fn instruction_sty() {
+        // ZeroPageX
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x94, 0x10]);
+        cpu.y = 3;
+        cpu.x = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0011), 3);
+
+        // Absolute
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x8C, 0x10, 0x01]);
+        cpu.y = 3;
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0110), 3);
+    }
+
+    #[test]
+    fn instruction_tas() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x9B, 0x10, 0x01]);
+        cpu.a = 0b11110011;
+        cpu.x = 0b00111111;
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 5);
+        assert_eq!(cpu.s, 0b00110011);
+        assert_eq!(cpu.read(&mut nes, 0x0111), 0b00000010);
+    }
+
+    #[test]
+    fn instruction_transfer() { // TAX, TAY, TSX, TXA, TYA
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xAA]);
+        cpu.a = 0x80;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0x80);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xA8]);
+        cpu.y = 0x01;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.y, 0x00);
+        assert_eq!(cpu.read_flag(Flag::Zero), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0xBA]);
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.x, 0xFD);
+        assert_eq!(cpu.read_flag(Flag::Negative), true);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x8A]);
+        cpu.x = 0x03;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x03);
+
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x98]);
+        cpu.y = 0x03;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0x03);
+    }
+
     #[test]
     fn instruction_txs_implied() {
         let (mut cpu, mut nes) = new_test_cpu(vec![0x9a]);
@@ -1081,4 +2844,261 @@ mod tests {
         assert_eq!(cpu.execute_instruction(&mut nes), 2);
         assert_eq!(cpu.s, 3);
     }
+
+    #[test]
+    fn instruction_xaa() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x8B, 0b00001111]);
+        cpu.a = 0x00;
+        cpu.x = 0xFF;
+        assert_eq!(cpu.execute_instruction(&mut nes), 2);
+        assert_eq!(cpu.a, 0b00001110);
+    }
+
+    #[test]
+    fn interrupt_disable_latency() {
+        // CLI: the IRQ is taken after the next instruction
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x58, 0xEA], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
+        nes.assert_irq(IrqSource::Mapper);
+        assert_eq!(cpu.tick(&mut nes), 2);
+        assert_eq!(cpu.pc, 0x8001);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0xA000);
+
+        // SEI: the IRQ is still taken right after it, and the pushed P has I set
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x78, 0xEA], 0x9000, 0x8000, 0xA000));
+        nes.assert_irq(IrqSource::Mapper);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0xA000);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00100100);
+
+        // PLP behaves like SEI and CLI
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x28, 0xEA], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
+        cpu.push_byte(&mut nes, 0b00000000);
+        nes.assert_irq(IrqSource::Mapper);
+        assert_eq!(cpu.tick(&mut nes), 4);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0xA000);
+
+        // RTI changes I immediately
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x40], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
+        cpu.push_word(&mut nes, 0x8100);
+        cpu.push_byte(&mut nes, 0b00000000);
+        nes.assert_irq(IrqSource::Mapper);
+        assert_eq!(cpu.tick(&mut nes), 6 + 7);
+        assert_eq!(cpu.pc, 0xA000);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 3), 0x81);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 2), 0x00);
+    }
+
+    #[test]
+    fn interrupt_irq() {
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0xEA, 0xEA, 0xEA], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
+        nes.assert_irq(IrqSource::Mapper);
+        nes.assert_irq(IrqSource::FrameCounter);
+
+        // Masked by I
+        assert_eq!(cpu.tick(&mut nes), 2);
+        assert_eq!(cpu.pc, 0x8001);
+
+        // The line stays asserted while any source holds it
+        nes.release_irq(IrqSource::Mapper);
+        cpu.write_flag(Flag::InterruptDisable, false);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0xA000);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00100000);
+        assert_eq!(cpu.read_flag(Flag::InterruptDisable), true);
+
+        nes.release_irq(IrqSource::FrameCounter);
+        cpu.write_flag(Flag::InterruptDisable, false);
+        assert_eq!(cpu.tick(&mut nes), 2);
+    }
+
+    #[test]
+    fn interrupt_nmi() {
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0xEA, 0xEA], 0x9000, 0x8000, 0xA000));
+        cpu.write_flag(Flag::InterruptDisable, true);
+        nes.set_nmi_line(true);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0x9000);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 3), 0x80);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 2), 0x01);
+        // B is clear in the pushed P
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00100100);
+
+        // Edge triggered: holding the line doesn't raise another NMI
+        assert_eq!(cpu.tick(&mut nes), 2);
+
+        nes.set_nmi_line(false);
+        assert_eq!(cpu.tick(&mut nes), 2);
+        nes.set_nmi_line(true);
+        assert_eq!(cpu.tick(&mut nes), 2 + 7);
+        assert_eq!(cpu.pc, 0x9000);
+    }
+
+    #[test]
+    fn interrupt_nmi_hijacks_brk() {
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x00, 0xFF], 0x9000, 0x8000, 0xA000));
+        // NMI is asserted while BRK is running
+        nes.set_nmi_line(true);
+        assert_eq!(cpu.execute_instruction(&mut nes), 7);
+        assert_eq!(cpu.pc, 0x9000);
+        // BRK's own state is pushed, with B set
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 2), 0x02);
+        assert_eq!(cpu.read(&mut nes, STACK_BASE + cpu.s as u16 + 1), 0b00110000);
+
+        // The NMI has been consumed
+        cpu.poll_interrupts(&nes);
+        assert_eq!(cpu.interrupt_poll, None);
+    }
+
+    #[test]
+    fn interrupt_vblank_nmi() {
+        // The NMI handler at $9000 counts NMIs.
+        let programs = [
+            // LDA #$80; STA $2000; JMP *
+            // INC $10; RTI
+            (vec![0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80], vec![0xE6, 0x10, 0x40]),
+            // LDA #$80; STA $2000; JMP $8000
+            // INC $10; LDA #$00; STA $2000; RTI
+            (vec![0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x00, 0x80], vec![0xE6, 0x10, 0xA9, 0x00, 0x8D, 0x00, 0x20, 0x40]),
+        ];
+        for cycle_stepped in [false, true] {
+            let mut counts = vec![];
+            for (program, handler) in programs.iter() {
+                let mut rom = program.clone();
+                rom.resize(0x1000, 0xEA);
+                rom.extend_from_slice(handler);
+                let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(rom, 0x9000, 0x8000, 0xA000));
+                cpu.set_cycle_stepped(cycle_stepped);
+
+                // 3 frames
+                while cpu.cycles < 90000 {
+                    cpu.tick(&mut nes);
+                }
+                counts.push(nes.read(0x0010));
+            }
+            // One NMI per frame, and more when NMI is enabled again during VBlank.
+            assert_eq!(counts[0], 3);
+            assert!(counts[1] > 3);
+        }
+    }
+
+    #[test]
+    fn interrupt_vblank_nmi_suppression() {
+        // LDA $2002; STA $11; JMP $8005
+        // INC $10; RTI
+        let mut rom = vec![0xAD, 0x02, 0x20, 0x85, 0x11, 0x4C, 0x05, 0x80];
+        rom.resize(0x1000, 0xEA);
+        rom.extend_from_slice(&[0xE6, 0x10, 0x40]);
+
+        for cycle_stepped in [false, true] {
+            let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(rom.clone(), 0x9000, 0x8000, 0xA000));
+            cpu.set_cycle_stepped(cycle_stepped);
+            nes.write(0x2000, 0x80);
+            // The 4th cycle of LDA reads PPUSTATUS 12 dots later, just before VBlank starts.
+            while (nes.ppu.scanline(), nes.ppu.dot()) != (240, 330) {
+                nes.clock(1);
+            }
+
+            while cpu.cycles < 20000 {
+                cpu.tick(&mut nes);
+            }
+            assert_eq!(nes.read(0x0011) & 0x80, 0);
+            // Only cycle stepping reads it on that dot, which suppresses the NMI.
+            assert_eq!(nes.read(0x0010), if cycle_stepped { 0 } else { 1 });
+        }
+    }
+
+    #[test]
+    fn oam_dma() {
+        // LDA #$02; STA $4014; STA $4014
+        for cycle_stepped in [false, true] {
+            let (mut cpu, mut nes) = new_test_cpu(vec![0xA9, 0x02, 0x8D, 0x14, 0x40, 0x8D, 0x14, 0x40]);
+            cpu.set_cycle_stepped(cycle_stepped);
+            for i in 0..=0xFF {
+                nes.write(0x0200 + i, (i as u8).wrapping_mul(3));
+            }
+            nes.write(0x2003, 0x10); // OAMADDR
+
+            assert_eq!(cpu.tick(&mut nes), 2);
+            // After the halt cycle on cycle 6, cycle 7 is odd and the CPU waits for alignment.
+            assert_eq!(cpu.tick(&mut nes), 4 + 514);
+            // Shift by a cycle so that the transfer starts on an even cycle.
+            cpu.cycles += 1;
+            assert_eq!(cpu.tick(&mut nes), 4 + 513);
+
+            // The transfer starts at OAMADDR and wraps around.
+            cpu.tick(&mut nes);
+            let oam = nes.ppu.oam();
+            assert_eq!(oam[0x10], 0x00);
+            assert_eq!(oam[0x11], 0x03);
+            assert_eq!(oam[0xFF], 0xEF_u8.wrapping_mul(3));
+            assert_eq!(oam[0x00], 0xF0_u8.wrapping_mul(3));
+            assert_eq!(oam[0x0F], 0xFF_u8.wrapping_mul(3));
+        }
+    }
+
+    #[test]
+    fn power_on() {
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![], 0x9000, 0xC123, 0xA000));
+        cpu.a = 0x03;
+        cpu.write(&mut nes, 0x0010, 0x03);
+        nes.power_on();
+        assert_eq!(cpu.power_on(&mut nes), 7);
+        assert_eq!(cpu.pc, 0xC123);
+        assert_eq!(cpu.a, 0);
+        assert_eq!(cpu.x, 0);
+        assert_eq!(cpu.y, 0);
+        assert_eq!(cpu.s, 0xFD);
+        assert_eq!(cpu.status, 0x34);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0);
+    }
+
+    #[test]
+    fn reset() {
+        let (mut cpu, mut nes) = new_test_cpu(new_test_rom_with_vectors(vec![0x02], 0x9000, 0xC123, 0xA000));
+        cpu.tick(&mut nes);
+        assert_eq!(cpu.is_halted(), true);
+
+        cpu.a = 0x03;
+        cpu.write(&mut nes, 0x0010, 0x03);
+        cpu.write(&mut nes, STACK_BASE + cpu.s as u16, 0x55);
+        assert_eq!(cpu.reset(&mut nes), 7);
+        assert_eq!(cpu.pc, 0xC123);
+        assert_eq!(cpu.s, 0xFD - 3);
+        assert_eq!(cpu.read_flag(Flag::InterruptDisable), true);
+        assert_eq!(cpu.is_halted(), false);
+        // The stack isn't written, and the other registers and RAM are kept
+        assert_eq!(cpu.read(&mut nes, 0x01FD), 0x55);
+        assert_eq!(cpu.a, 0x03);
+        assert_eq!(cpu.read(&mut nes, 0x0010), 0x03);
+    }
+
+    #[test]
+    fn stack() {
+        let (mut cpu, mut nes) = new_test_cpu(vec![0x48, 0x48, 0x68, 0x68]);
+        cpu.a = 0x80;
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        // The stack lives on page $01 and leaves the zero page alone
+        assert_eq!(cpu.read(&mut nes, 0x01FD), 0x80);
+        assert_eq!(cpu.read(&mut nes, 0x00FD), 0x00);
+        assert_eq!(cpu.s, 0xFC);
+
+        // S wraps around within page $01
+        cpu.s = 0x00;
+        cpu.a = 0x81;
+        assert_eq!(cpu.execute_instruction(&mut nes), 3);
+        assert_eq!(cpu.read(&mut nes, 0x0100), 0x81);
+        assert_eq!(cpu.s, 0xFF);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.a, 0x81);
+        assert_eq!(cpu.s, 0x00);
+        assert_eq!(cpu.execute_instruction(&mut nes), 4);
+        assert_eq!(cpu.read(&mut nes, 0x0101), 0x00);
+        assert_eq!(cpu.s, 0x01);
+    }
 }
@@ -0,0 +1,192 @@
+use std::fmt::{self, Formatter, Display};
+
+use super::instruction::{self, Addressing, Instruction, Opcode};
+
+/*
+ * 6502 disassembler
+ * It only looks at the bytes of instructions, so it never touches the hardware. The bytes come from
+ * a slice or a side-effect-free read such as Bus::peek.
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct Disassembly {
+    pub addr: u16,
+    pub opcode: Opcode,
+    pub mode: Addressing,
+    pub bytes: Vec<u8>, // The opcode followed by the operand
+}
+
+// Disassemble the instruction at `addr`, reading its bytes with `peek`.
+pub fn disassemble<F: Fn(u16) -> u8>(addr: u16, peek: F) -> Disassembly {
+    let Instruction(opcode, mode, _) = peek(addr).into();
+    let bytes = (0..=mode.operand_length())
+        .map(|i| peek(addr.wrapping_add(i)))
+        .collect();
+
+    Disassembly {
+        addr,
+        opcode,
+        mode,
+        bytes,
+    }
+}
+
+// Disassemble the instruction at the head of `bytes`, which are located at `addr`.
+// Returns None when `bytes` ends in the middle of the instruction.
+pub fn disassemble_bytes(bytes: &[u8], addr: u16) -> Option<Disassembly> {
+    let Instruction(_, mode, _) = (*bytes.first()?).into();
+    if bytes.len() <= mode.operand_length() as usize {
+        return None
+    }
+    Some(disassemble(addr, |a| bytes[a.wrapping_sub(addr) as usize]))
+}
+
+// Disassemble `bytes` from the head, as a sequence of instructions located at `addr`.
+// Trailing bytes which don't make up a whole instruction are left out.
+pub fn disassemble_all(bytes: &[u8], addr: u16) -> Vec<Disassembly> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while let Some(instruction) = disassemble_bytes(&bytes[offset..], addr.wrapping_add(offset as u16)) {
+        offset += instruction.length() as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+impl Disassembly {
+    pub fn length(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    pub fn mnemonic(&self) -> String {
+        self.opcode.to_string()
+    }
+
+    pub fn is_unofficial(&self) -> bool {
+        instruction::is_unofficial(self.bytes[0])
+    }
+
+    // The operand as a number: a byte, or a little-endian word for absolute and indirect addressing.
+    pub fn operand(&self) -> u16 {
+        match self.bytes.len() {
+            2 => self.bytes[1] as u16,
+            3 => (self.bytes[2] as u16) << 8 | self.bytes[1] as u16,
+            _ => 0,
+        }
+    }
+
+    // The operand in assembler syntax. Relative operands are shown as their targets.
+    pub fn format_operand(&self) -> String {
+        let operand = self.operand();
+        match self.mode {
+            Addressing::Implied => String::new(),
+            Addressing::Accumulator => "A".to_string(),
+            Addressing::Immediate => format!("#${:02X}", operand),
+            Addressing::ZeroPage => format!("${:02X}", operand),
+            Addressing::ZeroPageX => format!("${:02X},X", operand),
+            Addressing::ZeroPageY => format!("${:02X},Y", operand),
+            Addressing::Relative => format!("${:04X}", self.relative_target()),
+            Addressing::Absolute => format!("${:04X}", operand),
+            Addressing::AbsoluteX => format!("${:04X},X", operand),
+            Addressing::AbsoluteY => format!("${:04X},Y", operand),
+            Addressing::Indirect => format!("(${:04X})", operand),
+            Addressing::IndexedIndirect => format!("(${:02X},X)", operand),
+            Addressing::IndirectIndexed => format!("(${:02X}),Y", operand),
+        }
+    }
+
+    // Where a branch, JMP or JSR goes when it's taken. JMP (indirect) depends on memory, so it's None.
+    pub fn branch_target(&self) -> Option<u16> {
+        match (self.opcode, self.mode) {
+            (_, Addressing::Relative) => Some(self.relative_target()),
+            (Opcode::JMP, Addressing::Absolute) | (Opcode::JSR, Addressing::Absolute) => Some(self.operand()),
+            _ => None,
+        }
+    }
+
+    fn relative_target(&self) -> u16 {
+        self.addr.wrapping_add(self.length()).wrapping_add(self.operand() as u8 as i8 as u16)
+    }
+}
+
+// `LDA $10,X`
+impl Display for Disassembly {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let operand = self.format_operand();
+        if operand.is_empty() {
+            write!(f, "{}", self.opcode)
+        } else {
+            write!(f, "{} {}", self.opcode, operand)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+    use super::disassemble_all;
+    use super::disassemble_bytes;
+    use crate::instruction::{Addressing, Opcode};
+
+    fn text(bytes: &[u8], addr: u16) -> String {
+        disassemble_bytes(bytes, addr).unwrap().to_string()
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(text(&[0xEA], 0), "NOP");
+        assert_eq!(text(&[0x0A], 0), "ASL A");
+        assert_eq!(text(&[0xA9, 0x05], 0), "LDA #$05");
+        assert_eq!(text(&[0xA5, 0x10], 0), "LDA $10");
+        assert_eq!(text(&[0xB5, 0x10], 0), "LDA $10,X");
+        assert_eq!(text(&[0xB6, 0x10], 0), "LDX $10,Y");
+        assert_eq!(text(&[0xAD, 0x34, 0x12], 0), "LDA $1234");
+        assert_eq!(text(&[0xBD, 0x34, 0x12], 0), "LDA $1234,X");
+        assert_eq!(text(&[0xB9, 0x34, 0x12], 0), "LDA $1234,Y");
+        assert_eq!(text(&[0x6C, 0xFF, 0x02], 0), "JMP ($02FF)");
+        assert_eq!(text(&[0xA1, 0x10], 0), "LDA ($10,X)");
+        assert_eq!(text(&[0xB1, 0x10], 0), "LDA ($10),Y");
+        assert_eq!(text(&[0xD0, 0xFE], 0xC000), "BNE $C000");
+        assert_eq!(text(&[0x10, 0x7F], 0xC000), "BPL $C081");
+        assert_eq!(text(&[0xA7, 0x10], 0), "LAX $10");
+    }
+
+    #[test]
+    fn instruction() {
+        let instruction = disassemble_bytes(&[0x90, 0x80, 0xFF], 0x8000).unwrap();
+        assert_eq!(instruction.opcode, Opcode::BCC);
+        assert_eq!(instruction.mode, Addressing::Relative);
+        assert_eq!(instruction.bytes, vec![0x90, 0x80]);
+        assert_eq!(instruction.length(), 2);
+        assert_eq!(instruction.mnemonic(), "BCC");
+        assert_eq!(instruction.operand(), 0x80);
+        assert_eq!(instruction.branch_target(), Some(0x7F82));
+        assert!(!instruction.is_unofficial());
+
+        let instruction = disassemble_bytes(&[0x20, 0x00, 0xC0], 0x8000).unwrap();
+        assert_eq!(instruction.length(), 3);
+        assert_eq!(instruction.branch_target(), Some(0xC000));
+
+        assert_eq!(disassemble_bytes(&[0x6C, 0x00, 0xC0], 0).unwrap().branch_target(), None);
+        assert_eq!(disassemble_bytes(&[0xAD, 0x00, 0xC0], 0).unwrap().branch_target(), None);
+        assert!(disassemble_bytes(&[0x04, 0x00], 0).unwrap().is_unofficial());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(disassemble_bytes(&[], 0).is_none());
+        assert!(disassemble_bytes(&[0xAD, 0x00], 0).is_none());
+
+        let instructions = disassemble_all(&[0xA9, 0x05, 0xEA, 0x4C, 0x00], 0xFFFB);
+        let texts: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        assert_eq!(texts, vec!["LDA #$05", "NOP"]);
+        assert_eq!(instructions[1].addr, 0xFFFD);
+    }
+
+    #[test]
+    fn peek() {
+        // The operand wraps around the address space.
+        let instruction = disassemble(0xFFFF, |addr| if addr == 0xFFFF { 0x4C } else { addr as u8 + 1 });
+        assert_eq!(instruction.to_string(), "JMP $0201");
+    }
+}
@@ -10,7 +10,7 @@ use std::fmt::{self, Formatter, Display};
 #[derive(Debug)]
 pub struct Instruction(pub Opcode, pub Addressing, pub usize);
 
-#[derive(Debug)]
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum Opcode {
     ADC,
     AND,
@@ -70,39 +70,87 @@ pub enum Opcode {
     TYA,
 
     // Unofficial
+    ALR,
+    ANC,
+    ARR,
+    AXS,
+    DCP,
     ISC,
     KIL,
+    LAS,
+    LAX,
+    RLA,
+    RRA,
+    SAX,
+    SHA,
+    SHX,
+    SHY,
     SLO,
-
-    UNKNOWN(u8),
+    SRE,
+    TAS,
+    XAA,
 }
 
 impl Display for Opcode {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Self::UNKNOWN(byte) => write!(f, "UNKNWON(0x{:02X})", byte),
-            _ => write!(f, "{:?}", self),
-        }
+        write!(f, "{:?}", self)
+    }
+}
+
+// Opcodes which aren't in the official documentation, including the extra NOPs and SBC $EB.
+pub fn is_unofficial(opcode: u8) -> bool {
+    match Instruction::from(opcode) {
+        Instruction(Opcode::NOP, _, _) => opcode != 0xEA,
+        Instruction(Opcode::SBC, _, _) => opcode == 0xEB,
+        Instruction(opcode, _, _) => matches!(opcode,
+            Opcode::ALR | Opcode::ANC | Opcode::ARR | Opcode::AXS | Opcode::DCP | Opcode::ISC |
+            Opcode::KIL | Opcode::LAS | Opcode::LAX | Opcode::RLA | Opcode::RRA | Opcode::SAX |
+            Opcode::SHA | Opcode::SHX | Opcode::SHY | Opcode::SLO | Opcode::SRE | Opcode::TAS |
+            Opcode::XAA),
     }
 }
 
 impl From<u8> for Instruction {
     fn from(opcode: u8) -> Self {
         match opcode {
+            0x69 => Instruction(Opcode::ADC, Addressing::Immediate, 2),
+            0x65 => Instruction(Opcode::ADC, Addressing::ZeroPage, 3),
+            0x75 => Instruction(Opcode::ADC, Addressing::ZeroPageX, 4),
+            0x6D => Instruction(Opcode::ADC, Addressing::Absolute, 4),
+            0x7D => Instruction(Opcode::ADC, Addressing::AbsoluteX, 4),
+            0x79 => Instruction(Opcode::ADC, Addressing::AbsoluteY, 4),
+            0x61 => Instruction(Opcode::ADC, Addressing::IndexedIndirect, 6),
+            0x71 => Instruction(Opcode::ADC, Addressing::IndirectIndexed, 5),
+
+            0x29 => Instruction(Opcode::AND, Addressing::Immediate, 2),
+            0x25 => Instruction(Opcode::AND, Addressing::ZeroPage, 3),
+            0x35 => Instruction(Opcode::AND, Addressing::ZeroPageX, 4),
+            0x2D => Instruction(Opcode::AND, Addressing::Absolute, 4),
+            0x3D => Instruction(Opcode::AND, Addressing::AbsoluteX, 4),
+            0x39 => Instruction(Opcode::AND, Addressing::AbsoluteY, 4),
+            0x21 => Instruction(Opcode::AND, Addressing::IndexedIndirect, 6),
+            0x31 => Instruction(Opcode::AND, Addressing::IndirectIndexed, 5),
+
             0x0A => Instruction(Opcode::ASL, Addressing::Accumulator, 2),
             0x06 => Instruction(Opcode::ASL, Addressing::ZeroPage, 5),
             0x16 => Instruction(Opcode::ASL, Addressing::ZeroPageX, 6),
             0x0E => Instruction(Opcode::ASL, Addressing::Absolute, 6),
             0x1E => Instruction(Opcode::ASL, Addressing::AbsoluteX, 7),
 
+            // Branches
+            0x90 => Instruction(Opcode::BCC, Addressing::Relative, 2),
+            0xB0 => Instruction(Opcode::BCS, Addressing::Relative, 2),
+            0xF0 => Instruction(Opcode::BEQ, Addressing::Relative, 2),
             0x30 => Instruction(Opcode::BMI, Addressing::Relative, 2),
             0xD0 => Instruction(Opcode::BNE, Addressing::Relative, 2),
-
             0x10 => Instruction(Opcode::BPL, Addressing::Relative, 2),
+            0x50 => Instruction(Opcode::BVC, Addressing::Relative, 2),
+            0x70 => Instruction(Opcode::BVS, Addressing::Relative, 2),
 
-            0x00 => Instruction(Opcode::BRK, Addressing::Implied, 7),
+            0x24 => Instruction(Opcode::BIT, Addressing::ZeroPage, 3),
+            0x2C => Instruction(Opcode::BIT, Addressing::Absolute, 4),
 
-            0x50 => Instruction(Opcode::BVC, Addressing::Relative, 2),
+            0x00 => Instruction(Opcode::BRK, Addressing::Implied, 7),
 
             // Clear flags
             0x18 => Instruction(Opcode::CLC, Addressing::Implied, 2),
@@ -120,15 +168,46 @@ impl From<u8> for Instruction {
             0xC1 => Instruction(Opcode::CMP, Addressing::IndexedIndirect, 6),
             0xD1 => Instruction(Opcode::CMP, Addressing::IndirectIndexed, 5),
 
+            0xE0 => Instruction(Opcode::CPX, Addressing::Immediate, 2),
+            0xE4 => Instruction(Opcode::CPX, Addressing::ZeroPage, 3),
+            0xEC => Instruction(Opcode::CPX, Addressing::Absolute, 4),
+
+            0xC0 => Instruction(Opcode::CPY, Addressing::Immediate, 2),
+            0xC4 => Instruction(Opcode::CPY, Addressing::ZeroPage, 3),
+            0xCC => Instruction(Opcode::CPY, Addressing::Absolute, 4),
+
+            // Decrement
             0xC6 => Instruction(Opcode::DEC, Addressing::ZeroPage, 5),
             0xD6 => Instruction(Opcode::DEC, Addressing::ZeroPageX, 6),
+            0xCE => Instruction(Opcode::DEC, Addressing::Absolute, 6),
+            0xDE => Instruction(Opcode::DEC, Addressing::AbsoluteX, 7),
 
+            0xCA => Instruction(Opcode::DEX, Addressing::Implied, 2),
             0x88 => Instruction(Opcode::DEY, Addressing::Implied, 2),
+
+            0x49 => Instruction(Opcode::EOR, Addressing::Immediate, 2),
+            0x45 => Instruction(Opcode::EOR, Addressing::ZeroPage, 3),
+            0x55 => Instruction(Opcode::EOR, Addressing::ZeroPageX, 4),
+            0x4D => Instruction(Opcode::EOR, Addressing::Absolute, 4),
+            0x5D => Instruction(Opcode::EOR, Addressing::AbsoluteX, 4),
+            0x59 => Instruction(Opcode::EOR, Addressing::AbsoluteY, 4),
+            0x41 => Instruction(Opcode::EOR, Addressing::IndexedIndirect, 6),
+            0x51 => Instruction(Opcode::EOR, Addressing::IndirectIndexed, 5),
+
+            // Increment
+            0xE6 => Instruction(Opcode::INC, Addressing::ZeroPage, 5),
+            0xF6 => Instruction(Opcode::INC, Addressing::ZeroPageX, 6),
+            0xEE => Instruction(Opcode::INC, Addressing::Absolute, 6),
+            0xFE => Instruction(Opcode::INC, Addressing::AbsoluteX, 7),
+
             0xE8 => Instruction(Opcode::INX, Addressing::Implied, 2),
+            0xC8 => Instruction(Opcode::INY, Addressing::Implied, 2),
 
             0x4C => Instruction(Opcode::JMP, Addressing::Absolute, 3),
+            0x6C => Instruction(Opcode::JMP, Addressing::Indirect, 5),
             0x20 => Instruction(Opcode::JSR, Addressing::Absolute, 6),
 
+            // Load
             0xA9 => Instruction(Opcode::LDA, Addressing::Immediate, 2),
             0xA5 => Instruction(Opcode::LDA, Addressing::ZeroPage, 3),
             0xB5 => Instruction(Opcode::LDA, Addressing::ZeroPageX, 4),
@@ -136,16 +215,122 @@ impl From<u8> for Instruction {
             0xBD => Instruction(Opcode::LDA, Addressing::AbsoluteX, 4),
             0xB9 => Instruction(Opcode::LDA, Addressing::AbsoluteY, 4),
             0xA1 => Instruction(Opcode::LDA, Addressing::IndexedIndirect, 6),
-            0xB1 => Instruction(Opcode::LDA, Addressing::IndirectIndexed, 4),
+            0xB1 => Instruction(Opcode::LDA, Addressing::IndirectIndexed, 5),
 
             0xA2 => Instruction(Opcode::LDX, Addressing::Immediate, 2),
+            0xA6 => Instruction(Opcode::LDX, Addressing::ZeroPage, 3),
+            0xB6 => Instruction(Opcode::LDX, Addressing::ZeroPageY, 4),
+            0xAE => Instruction(Opcode::LDX, Addressing::Absolute, 4),
+            0xBE => Instruction(Opcode::LDX, Addressing::AbsoluteY, 4),
+
             0xA0 => Instruction(Opcode::LDY, Addressing::Immediate, 2),
+            0xA4 => Instruction(Opcode::LDY, Addressing::ZeroPage, 3),
+            0xB4 => Instruction(Opcode::LDY, Addressing::ZeroPageX, 4),
+            0xAC => Instruction(Opcode::LDY, Addressing::Absolute, 4),
+            0xBC => Instruction(Opcode::LDY, Addressing::AbsoluteX, 4),
+
+            0x4A => Instruction(Opcode::LSR, Addressing::Accumulator, 2),
+            0x46 => Instruction(Opcode::LSR, Addressing::ZeroPage, 5),
+            0x56 => Instruction(Opcode::LSR, Addressing::ZeroPageX, 6),
+            0x4E => Instruction(Opcode::LSR, Addressing::Absolute, 6),
+            0x5E => Instruction(Opcode::LSR, Addressing::AbsoluteX, 7),
+
+            0xEA => Instruction(Opcode::NOP, Addressing::Implied, 2),
+
+            0x09 => Instruction(Opcode::ORA, Addressing::Immediate, 2),
+            0x05 => Instruction(Opcode::ORA, Addressing::ZeroPage, 3),
+            0x15 => Instruction(Opcode::ORA, Addressing::ZeroPageX, 4),
+            0x0D => Instruction(Opcode::ORA, Addressing::Absolute, 4),
+            0x1D => Instruction(Opcode::ORA, Addressing::AbsoluteX, 4),
+            0x19 => Instruction(Opcode::ORA, Addressing::AbsoluteY, 4),
+            0x01 => Instruction(Opcode::ORA, Addressing::IndexedIndirect, 6),
+            0x11 => Instruction(Opcode::ORA, Addressing::IndirectIndexed, 5),
+
+            // Stack
+            0x48 => Instruction(Opcode::PHA, Addressing::Implied, 3),
+            0x08 => Instruction(Opcode::PHP, Addressing::Implied, 3),
+            0x68 => Instruction(Opcode::PLA, Addressing::Implied, 4),
+            0x28 => Instruction(Opcode::PLP, Addressing::Implied, 4),
+
+            0x2A => Instruction(Opcode::ROL, Addressing::Accumulator, 2),
+            0x26 => Instruction(Opcode::ROL, Addressing::ZeroPage, 5),
+            0x36 => Instruction(Opcode::ROL, Addressing::ZeroPageX, 6),
+            0x2E => Instruction(Opcode::ROL, Addressing::Absolute, 6),
+            0x3E => Instruction(Opcode::ROL, Addressing::AbsoluteX, 7),
+
+            0x6A => Instruction(Opcode::ROR, Addressing::Accumulator, 2),
+            0x66 => Instruction(Opcode::ROR, Addressing::ZeroPage, 5),
+            0x76 => Instruction(Opcode::ROR, Addressing::ZeroPageX, 6),
+            0x6E => Instruction(Opcode::ROR, Addressing::Absolute, 6),
+            0x7E => Instruction(Opcode::ROR, Addressing::AbsoluteX, 7),
+
+            0x40 => Instruction(Opcode::RTI, Addressing::Implied, 6),
+            0x60 => Instruction(Opcode::RTS, Addressing::Implied, 6),
+
+            0xE9 => Instruction(Opcode::SBC, Addressing::Immediate, 2),
+            0xE5 => Instruction(Opcode::SBC, Addressing::ZeroPage, 3),
+            0xF5 => Instruction(Opcode::SBC, Addressing::ZeroPageX, 4),
+            0xED => Instruction(Opcode::SBC, Addressing::Absolute, 4),
+            0xFD => Instruction(Opcode::SBC, Addressing::AbsoluteX, 4),
+            0xF9 => Instruction(Opcode::SBC, Addressing::AbsoluteY, 4),
+            0xE1 => Instruction(Opcode::SBC, Addressing::IndexedIndirect, 6),
+            0xF1 => Instruction(Opcode::SBC, Addressing::IndirectIndexed, 5),
+
+            // Set flags
+            0x38 => Instruction(Opcode::SEC, Addressing::Implied, 2),
+            0xF8 => Instruction(Opcode::SED, Addressing::Implied, 2),
             0x78 => Instruction(Opcode::SEI, Addressing::Implied, 2),
+
+            // Store
+            0x85 => Instruction(Opcode::STA, Addressing::ZeroPage, 3),
+            0x95 => Instruction(Opcode::STA, Addressing::ZeroPageX, 4),
             0x8D => Instruction(Opcode::STA, Addressing::Absolute, 4),
+            0x9D => Instruction(Opcode::STA, Addressing::AbsoluteX, 5),
+            0x99 => Instruction(Opcode::STA, Addressing::AbsoluteY, 5),
+            0x81 => Instruction(Opcode::STA, Addressing::IndexedIndirect, 6),
+            0x91 => Instruction(Opcode::STA, Addressing::IndirectIndexed, 6),
+
+            0x86 => Instruction(Opcode::STX, Addressing::ZeroPage, 3),
+            0x96 => Instruction(Opcode::STX, Addressing::ZeroPageY, 4),
+            0x8E => Instruction(Opcode::STX, Addressing::Absolute, 4),
+
+            0x84 => Instruction(Opcode::STY, Addressing::ZeroPage, 3),
+            0x94 => Instruction(Opcode::STY, Addressing::ZeroPageX, 4),
+            0x8C => Instruction(Opcode::STY, Addressing::Absolute, 4),
+
+            // Transfer
+            0xAA => Instruction(Opcode::TAX, Addressing::Implied, 2),
+            0xA8 => Instruction(Opcode::TAY, Addressing::Implied, 2),
+            0xBA => Instruction(Opcode::TSX, Addressing::Implied, 2),
+            0x8A => Instruction(Opcode::TXA, Addressing::Implied, 2),
             0x9A => Instruction(Opcode::TXS, Addressing::Implied, 2),
+            0x98 => Instruction(Opcode::TYA, Addressing::Implied, 2),
 
             // Unofficial instructions
+            0x4B => Instruction(Opcode::ALR, Addressing::Immediate, 2),
+
+            0x0B => Instruction(Opcode::ANC, Addressing::Immediate, 2),
+            0x2B => Instruction(Opcode::ANC, Addressing::Immediate, 2),
+
+            0x6B => Instruction(Opcode::ARR, Addressing::Immediate, 2),
+
+            0xCB => Instruction(Opcode::AXS, Addressing::Immediate, 2),
+
+            0xC7 => Instruction(Opcode::DCP, Addressing::ZeroPage, 5),
+            0xD7 => Instruction(Opcode::DCP, Addressing::ZeroPageX, 6),
+            0xCF => Instruction(Opcode::DCP, Addressing::Absolute, 6),
+            0xDF => Instruction(Opcode::DCP, Addressing::AbsoluteX, 7),
+            0xDB => Instruction(Opcode::DCP, Addressing::AbsoluteY, 7),
+            0xC3 => Instruction(Opcode::DCP, Addressing::IndexedIndirect, 8),
+            0xD3 => Instruction(Opcode::DCP, Addressing::IndirectIndexed, 8),
+
+            0xE7 => Instruction(Opcode::ISC, Addressing::ZeroPage, 5),
+            0xF7 => Instruction(Opcode::ISC, Addressing::ZeroPageX, 6),
+            0xEF => Instruction(Opcode::ISC, Addressing::Absolute, 6),
             0xFF => Instruction(Opcode::ISC, Addressing::AbsoluteX, 7),
+            0xFB => Instruction(Opcode::ISC, Addressing::AbsoluteY, 7),
+            0xE3 => Instruction(Opcode::ISC, Addressing::IndexedIndirect, 8),
+            0xF3 => Instruction(Opcode::ISC, Addressing::IndirectIndexed, 8),
 
             0x02 => Instruction(Opcode::KIL, Addressing::Implied, 2),
             0x12 => Instruction(Opcode::KIL, Addressing::Implied, 2),
@@ -160,7 +345,65 @@ impl From<u8> for Instruction {
             0xD2 => Instruction(Opcode::KIL, Addressing::Implied, 2),
             0xF2 => Instruction(Opcode::KIL, Addressing::Implied, 2),
 
+            0xBB => Instruction(Opcode::LAS, Addressing::AbsoluteY, 4),
+
+            0xAB => Instruction(Opcode::LAX, Addressing::Immediate, 2),
+            0xA7 => Instruction(Opcode::LAX, Addressing::ZeroPage, 3),
+            0xB7 => Instruction(Opcode::LAX, Addressing::ZeroPageY, 4),
+            0xAF => Instruction(Opcode::LAX, Addressing::Absolute, 4),
+            0xBF => Instruction(Opcode::LAX, Addressing::AbsoluteY, 4),
+            0xA3 => Instruction(Opcode::LAX, Addressing::IndexedIndirect, 6),
+            0xB3 => Instruction(Opcode::LAX, Addressing::IndirectIndexed, 5),
+
+            0x27 => Instruction(Opcode::RLA, Addressing::ZeroPage, 5),
+            0x37 => Instruction(Opcode::RLA, Addressing::ZeroPageX, 6),
+            0x2F => Instruction(Opcode::RLA, Addressing::Absolute, 6),
+            0x3F => Instruction(Opcode::RLA, Addressing::AbsoluteX, 7),
+            0x3B => Instruction(Opcode::RLA, Addressing::AbsoluteY, 7),
+            0x23 => Instruction(Opcode::RLA, Addressing::IndexedIndirect, 8),
+            0x33 => Instruction(Opcode::RLA, Addressing::IndirectIndexed, 8),
+
+            0x67 => Instruction(Opcode::RRA, Addressing::ZeroPage, 5),
+            0x77 => Instruction(Opcode::RRA, Addressing::ZeroPageX, 6),
+            0x6F => Instruction(Opcode::RRA, Addressing::Absolute, 6),
+            0x7F => Instruction(Opcode::RRA, Addressing::AbsoluteX, 7),
+            0x7B => Instruction(Opcode::RRA, Addressing::AbsoluteY, 7),
+            0x63 => Instruction(Opcode::RRA, Addressing::IndexedIndirect, 8),
+            0x73 => Instruction(Opcode::RRA, Addressing::IndirectIndexed, 8),
+
+            0x87 => Instruction(Opcode::SAX, Addressing::ZeroPage, 3),
+            0x97 => Instruction(Opcode::SAX, Addressing::ZeroPageY, 4),
+            0x8F => Instruction(Opcode::SAX, Addressing::Absolute, 4),
+            0x83 => Instruction(Opcode::SAX, Addressing::IndexedIndirect, 6),
+
+            0xEB => Instruction(Opcode::SBC, Addressing::Immediate, 2),
+
+            0x9F => Instruction(Opcode::SHA, Addressing::AbsoluteY, 5),
+            0x93 => Instruction(Opcode::SHA, Addressing::IndirectIndexed, 6),
+
+            0x9E => Instruction(Opcode::SHX, Addressing::AbsoluteY, 5),
+
+            0x9C => Instruction(Opcode::SHY, Addressing::AbsoluteX, 5),
+
+            0x07 => Instruction(Opcode::SLO, Addressing::ZeroPage, 5),
+            0x17 => Instruction(Opcode::SLO, Addressing::ZeroPageX, 6),
+            0x0F => Instruction(Opcode::SLO, Addressing::Absolute, 6),
+            0x1F => Instruction(Opcode::SLO, Addressing::AbsoluteX, 7),
+            0x1B => Instruction(Opcode::SLO, Addressing::AbsoluteY, 7),
             0x03 => Instruction(Opcode::SLO, Addressing::IndexedIndirect, 8),
+            0x13 => Instruction(Opcode::SLO, Addressing::IndirectIndexed, 8),
+
+            0x47 => Instruction(Opcode::SRE, Addressing::ZeroPage, 5),
+            0x57 => Instruction(Opcode::SRE, Addressing::ZeroPageX, 6),
+            0x4F => Instruction(Opcode::SRE, Addressing::Absolute, 6),
+            0x5F => Instruction(Opcode::SRE, Addressing::AbsoluteX, 7),
+            0x5B => Instruction(Opcode::SRE, Addressing::AbsoluteY, 7),
+            0x43 => Instruction(Opcode::SRE, Addressing::IndexedIndirect, 8),
+            0x53 => Instruction(Opcode::SRE, Addressing::IndirectIndexed, 8),
+
+            0x9B => Instruction(Opcode::TAS, Addressing::AbsoluteY, 5),
+
+            0x8B => Instruction(Opcode::XAA, Addressing::Immediate, 2),
 
             0x04 => Instruction(Opcode::NOP, Addressing::ZeroPage, 3),
             0x0C => Instruction(Opcode::NOP, Addressing::Absolute, 4),
@@ -189,13 +432,11 @@ impl From<u8> for Instruction {
             0xF4 => Instruction(Opcode::NOP, Addressing::ZeroPageX, 4),
             0xFA => Instruction(Opcode::NOP, Addressing::Implied, 2),
             0xFC => Instruction(Opcode::NOP, Addressing::AbsoluteX, 4),
-
-            _ => Instruction(Opcode::UNKNOWN(opcode), Addressing::UNKNOWN, 0),
         }
     }
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,Clone,Copy,PartialEq)]
 pub enum Addressing {
     Accumulator,
     Absolute,
@@ -210,6 +451,15 @@ pub enum Addressing {
     ZeroPage,
     ZeroPageX,
     ZeroPageY,
+}
 
-    UNKNOWN,
+impl Addressing {
+    // Number of bytes following the opcode
+    pub fn operand_length(&self) -> u16 {
+        match self {
+            Addressing::Accumulator | Addressing::Implied => 0,
+            Addressing::Absolute | Addressing::AbsoluteX | Addressing::AbsoluteY | Addressing::Indirect => 2,
+            _ => 1,
+        }
+    }
 }
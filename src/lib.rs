@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate log;
+
+pub mod bus;
+pub mod cassette;
+pub mod cpu;
+pub mod disasm;
+pub mod instruction;
+pub mod nes;
+pub mod palette;
+pub mod ppu;
+pub mod ppu_register_bus;
+pub mod trace;
@@ -14,42 +14,75 @@ use piston_window::{PistonWindow, WindowSettings, Texture, TextureContext, Textu
 use piston_window::OpenGL;
 use piston_window::G2dTexture;
 use piston_window::{RenderEvent, Transformed}; // render_args(), scale()
+use piston_window::{Button, Key, PressEvent}; // press_args()
 use piston_window::{clear, image as piston_image};
 
-mod cassette;
-mod cpu;
-mod instruction;
-mod ppu;
-mod ppu_register_bus;
-mod nes;
+use rust_nes::cassette;
+use rust_nes::cpu;
+use rust_nes::disasm;
+use rust_nes::nes;
+use rust_nes::palette;
+use rust_nes::ppu;
+use rust_nes::trace;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    env::set_var("RUST_LOG", "debug,gfx_device_gl=warn,winit=warn");
-    env_logger::init();
+    // Usage: rust-nes [--cycle-stepped] [--palette=PAL] ROM
+    //        rust-nes --nestest ROM [GOLDEN_LOG]
+    //        rust-nes --disasm ROM
+    // --cycle-stepped runs the PPU on every CPU bus access instead of after each instruction.
+    // --palette loads colors from a .pal file (192 or 1536 bytes) instead of the built-in palette.
+    // --nestest runs nestest.nes from $C000 without a window. It prints the trace, or compares it
+    // with GOLDEN_LOG (nestest.log) and stops at the first line which differs.
+    // --disasm prints the disassembly of PRG ROM.
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cycle_stepped = args.iter().any(|arg| arg == "--cycle-stepped");
+    let nestest = args.iter().any(|arg| arg == "--nestest");
+    let disasm = args.iter().any(|arg| arg == "--disasm");
+    let palette_filename = args.iter().find_map(|arg| arg.strip_prefix("--palette="));
+    let filenames: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        error!("ROM filename is required");
-        return Err("ROM filename is required".into());
+    if nestest || disasm {
+        env::set_var("RUST_LOG", "info");
+    } else {
+        env::set_var("RUST_LOG", "debug,gfx_device_gl=warn,winit=warn");
     }
-    let rom_filename = &args[1];
+    env_logger::init();
 
-    debug!("ROM file = {}", rom_filename);
+    let rom_filename = match filenames.first() {
+        Some(filename) => filename,
+        None => {
+            error!("ROM filename is required");
+            return Err("ROM filename is required".into());
+        },
+    };
 
-    let mut f = File::open(rom_filename)?;
-    let mut buf = Vec::new();
-    let _ = f.read_to_end(&mut buf)?;
+    debug!("ROM file = {}", rom_filename);
 
-    let cassette = cassette::Cassette::new(buf);
+    let cassette = load_cassette(rom_filename)?;
 
-    if !cassette.is_ines() {
-        error!("ROM must be iNES format");
-        return Err("ROM must be iNES format".into());
+    if disasm {
+        return dump_prg_rom(&cassette);
     }
 
     let mut nes = nes::Nes::new(cassette);
+
+    if nestest {
+        return run_nestest(&mut nes, filenames.get(1).map(|filename| filename.as_str()));
+    }
+
+    // The PPU outputs color indices, and the palette turns them into RGB as a TV does.
+    let palette = match palette_filename {
+        Some(filename) => {
+            debug!("Palette file = {}", filename);
+            palette::Palette::from_pal(&std::fs::read(filename)?)?
+        },
+        None => palette::Palette::new(),
+    };
+
     let mut cpu = cpu::Cpu::new();
-    let mut ppu = ppu::Ppu::new();
+    cpu.set_cycle_stepped(cycle_stepped);
+
+    cpu.power_on(&mut nes);
 
     //display_sprites(&nes);
 
@@ -77,16 +110,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         ).unwrap();
 
     while let Some(e) = window.next() {
-        if let Some(_) = e.render_args() {
-            let mut rendered = false;
+        match e.press_args() {
+            // R: reset button
+            Some(Button::Keyboard(Key::R)) => {
+                info!("Reset");
+                nes.reset();
+                cpu.reset(&mut nes);
+            },
+            // P: power cycle
+            Some(Button::Keyboard(Key::P)) => {
+                info!("Power cycle");
+                nes.power_on();
+                cpu.power_on(&mut nes);
+            },
+            _ => {},
+        }
+
+        if e.render_args().is_some() {
+            let mut rendered = cpu.is_halted();
             while !rendered {
-                let cycle = cpu.tick(&mut nes);
-                rendered = ppu.step(&mut nes, cycle);
+                cpu.tick(&mut nes);
+                if cpu.is_halted() {
+                    error!("Emulation stopped because the CPU is halted");
+                    cpu.dump();
+                    break;
+                }
+                rendered = nes.take_rendered();
             }
 
             for x in 0..ppu::VISIBLE_SCREEN_WIDTH {
                 for y in 0..ppu::VISIBLE_SCREEN_HEIGHT {
-                    let color = ppu.screen[y][x];
+                    let color = palette.pixel_rgb(nes.ppu.pixels[y][x]);
                     canvas.put_pixel(
                         x as u32,
                         y as u32,
@@ -107,7 +161,73 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+fn load_cassette(filename: &str) -> Result<cassette::Cassette, Box<dyn std::error::Error>> {
+    let mut f = File::open(filename)?;
+    let mut buf = Vec::new();
+    let _ = f.read_to_end(&mut buf)?;
+
+    let cassette = cassette::Cassette::new(buf);
+
+    if !cassette.is_ines() {
+        error!("ROM must be iNES format");
+        return Err("ROM must be iNES format".into());
+    }
+
+    Ok(cassette)
+}
+
+// PRG ROM is placed at the end of the address space, where the vectors are, and NROM-128 is shown at $C000.
+fn dump_prg_rom(cassette: &cassette::Cassette) -> Result<(), Box<dyn std::error::Error>> {
+    let prg_rom = &cassette.prg_rom[..cassette.prg_rom_size()];
+    let base = (0x10000 - prg_rom.len()) as u16;
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for instruction in disasm::disassemble_all(prg_rom, base) {
+        let bytes: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        if writeln!(out, "{:04X}  {:<9} {}", instruction.addr, bytes.join(" "), instruction).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Without a golden log, print the trace until the CPU halts or the output is closed.
+fn run_nestest(nes: &mut nes::Nes, golden_log: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut cpu = cpu::Cpu::new();
+    trace::start_nestest(&mut cpu, nes);
+
+    let filename = match golden_log {
+        Some(filename) => filename,
+        None => {
+            let stdout = std::io::stdout();
+            let mut out = stdout.lock();
+            while !cpu.is_halted() {
+                if writeln!(out, "{}", trace::nestest_line(&cpu, nes)).is_err() {
+                    break;
+                }
+                cpu.tick(nes);
+            }
+            return Ok(());
+        },
+    };
+
+    let golden_log = std::fs::read_to_string(filename)?;
+    match trace::compare_with_log(&mut cpu, nes, golden_log.lines()) {
+        Ok(lines) => {
+            info!("All {} lines match {}", lines, filename);
+            Ok(())
+        },
+        Err(divergence) => {
+            error!("{}", divergence);
+            Err(format!("Diverged from {} at line {}", filename, divergence.line).into())
+        },
+    }
+}
+
 // https://github.com/PistonDevelopers/piston-examples/blob/master/src/paint.rs
+#[allow(dead_code)]
 fn display_sprites(nes: &nes::Nes) {
     const COLORS: [image::Rgba::<u8>; 4] = [
         image::Rgba([0, 0, 0, 255]),
@@ -140,7 +260,7 @@ fn display_sprites(nes: &nes::Nes) {
         ).unwrap();
 
     while let Some(e) = window.next() {
-        if let Some(_) = e.render_args() {
+        if e.render_args().is_some() {
             for i in 0..=0xFF {
                 let sprite = nes.get_sprite(i);
 
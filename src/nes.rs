@@ -1,23 +1,41 @@
+use super::bus::Bus;
 use super::cassette::Cassette;
+use super::cassette::Mirroring;
 use super::cassette::Sprite;
+use super::ppu::Ppu;
 use super::ppu_register_bus::PpuRegisterBus;
-use super::cpu::Interruption;
+use super::cpu::IrqSource;
+
+const RAM_SIZE: usize = 0x0800;
+pub const PRG_ROM_BASE: u16 = 0x8000;
 
 /*
  * Container for sharable hardwares, such as PPU registers and cassette.
+ * It is also the bus of the CPU, with the NES memory map.
  */
 pub struct Nes {
+    ram: [u8; RAM_SIZE],
     cassette: Cassette,
+    pub ppu: Ppu,
     pub ppu_register_bus: PpuRegisterBus,
-    pub cpu_interruption: Interruption,
+    // Set when the PPU has rendered lines since the last take_rendered()
+    rendered: bool,
+
+    // CPU interrupt lines. true means asserted. The PPU also pulls NMI, see nmi_line().
+    nmi_line: bool,
+    irq_lines: u8, // One bit per IrqSource
 }
 
 impl Nes {
     pub fn new(cassette: Cassette) -> Self {
         Self {
+            ram: [0; RAM_SIZE],
             cassette,
+            ppu: Ppu::new(),
             ppu_register_bus: PpuRegisterBus::new(),
-            cpu_interruption: Interruption::None,
+            rendered: false,
+            nmi_line: false,
+            irq_lines: 0,
         }
     }
 
@@ -30,26 +48,125 @@ impl Nes {
             prg_rom,
         ].concat();
 
-        for _ in 0..=(0x4000-len) {
-            data.push(0);
-        }
+        data.resize(data.len() + 0x4000 - len + 1, 0);
 
         Self {
+            ram: [0; RAM_SIZE],
             cassette: Cassette::new(data),
+            ppu: Ppu::new(),
             ppu_register_bus: PpuRegisterBus::new(),
-            cpu_interruption: Interruption::None,
+            rendered: false,
+            nmi_line: false,
+            irq_lines: 0,
         }
     }
 
+    // Power cycle: everything except the cassette goes back to its power-up state.
+    pub fn power_on(&mut self) {
+        self.ram = [0; RAM_SIZE];
+        self.ppu = Ppu::new();
+        self.ppu_register_bus = PpuRegisterBus::new();
+        self.rendered = false;
+        self.nmi_line = false;
+        self.irq_lines = 0;
+    }
+
+    // Side effects of the reset button on hardware other than the CPU.
+    // https://wiki.nesdev.com/w/index.php/PPU_power_up_state
+    pub fn reset(&mut self) {
+        self.ppu_register_bus.reset();
+        // Reset silences the APU, which also acknowledges its interrupts.
+        self.release_irq(IrqSource::FrameCounter);
+        self.release_irq(IrqSource::Dmc);
+    }
+
+    pub fn take_rendered(&mut self) -> bool {
+        std::mem::replace(&mut self.rendered, false)
+    }
+
+    pub fn set_nmi_line(&mut self, asserted: bool) {
+        self.nmi_line = asserted;
+    }
+
+    pub fn assert_irq(&mut self, source: IrqSource) {
+        self.irq_lines |= u8::from(source);
+    }
+
+    pub fn release_irq(&mut self, source: IrqSource) {
+        self.irq_lines &= !u8::from(source);
+    }
+
+    // 16 KiB PRG ROM (NROM-128) is mirrored into both halves of 0x8000-0xFFFF.
     pub fn read_program(&self, addr: u16) -> u8 {
-        self.cassette.prg_rom[addr as usize]
+        self.cassette.prg_rom[addr as usize % self.cassette.prg_rom_size()]
     }
 
-    pub fn read_chr_rom(&self, addr: u16) -> u8 {
-        self.cassette.chr_rom[addr as usize]
+    // For mappers which switch mirroring while the PPU is running. The PPU sees it from the next access.
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.cassette.set_mirroring(mirroring);
     }
 
     pub fn get_sprite(&self, id: u8) -> &Sprite {
-        &self.cassette.sprites[id as usize]
+        self.cassette.get_sprite(id)
+    }
+}
+
+impl Bus for Nes {
+    // https://wiki.nesdev.com/w/index.php/CPU_memory_map
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x07FF => self.ram[addr as usize],
+            0x0800..=0x0FFF => self.ram[(addr - 0x0800) as usize],
+            0x1000..=0x17FF => self.ram[(addr - 0x1000) as usize],
+            0x1800..=0x1FFF => self.ram[(addr - 0x1800) as usize],
+            // PPU registers are mirrored every 8 bytes.
+            0x2000..=0x3FFF => self.ppu.cpu_read(&self.cassette, &mut self.ppu_register_bus, 0x2000 | addr & 0x0007),
+            0x4000..=0x401F => { warn!("Reading CPU address 0x4000-0x401F is not implemented"); 0 },
+            0x4020..=0x7FFF => { warn!("Reading CPU address 0x4020-0x7FFF is not implemented"); 0 }, // 拡張ROM, 拡張RAM
+            PRG_ROM_BASE..=0xFFFF => self.read_program(addr-PRG_ROM_BASE),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x07FF => self.ram[addr as usize] = data,
+            0x0800..=0x0FFF => self.ram[(addr - 0x0800) as usize] = data,
+            0x1000..=0x17FF => self.ram[(addr - 0x1000) as usize] = data,
+            0x1800..=0x1FFF => self.ram[(addr - 0x1800) as usize] = data,
+            0x2000..=0x3FFF => self.ppu.cpu_write(&self.cassette, &mut self.ppu_register_bus, 0x2000 | addr & 0x0007, data),
+            0x4014 => self.ppu_register_bus.request_oam_dma(data),
+            0x4000..=0x401F => warn!("Writing CPU address 0x4000-0x401F is not implemented"),
+            0x4020..=0xFFFF => self.cassette.write(addr, data),
+        }
+    }
+
+    // PPU registers have read side effects; they read as 0 here. APU and I/O registers read as 0xFF,
+    // which is what Nintendulator shows for them in nestest.log.
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.ram[(addr & 0x07FF) as usize],
+            0x4000..=0x401F => 0xFF,
+            PRG_ROM_BASE..=0xFFFF => self.read_program(addr-PRG_ROM_BASE),
+            _ => 0,
+        }
+    }
+
+    // The PPU runs 3 dots per CPU cycle.
+    fn clock(&mut self, cpu_cycle: usize) {
+        if self.ppu.step(&self.cassette, &mut self.ppu_register_bus, cpu_cycle) {
+            self.rendered = true;
+        }
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.nmi_line || self.ppu_register_bus.nmi_output()
+    }
+
+    fn irq_line(&self) -> bool {
+        self.irq_lines != 0
+    }
+
+    fn take_oam_dma(&mut self) -> Option<u8> {
+        self.ppu_register_bus.take_oam_dma()
     }
 }
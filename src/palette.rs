@@ -0,0 +1,142 @@
+/*
+ * NES master palette
+ * https://wiki.nesdev.com/w/index.php/PPU_palettes
+ *
+ * The PPU outputs 6-bit color indices, and the TV turns them into colors. There is no single right
+ * RGB value for each index, so this is one of the common approximations of an NTSC 2C02.
+ */
+
+pub const COLORS: usize = 64;
+
+pub const NTSC_PALETTE: [[u8; 3]; COLORS] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1E, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5C, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3C, 0x18, 0x00],
+    [0x20, 0x2A, 0x00], [0x08, 0x3A, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3C, 0x00],
+    [0x00, 0x32, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98], [0x08, 0x4C, 0xC4], [0x30, 0x32, 0xEC], [0x5C, 0x1E, 0xE4],
+    [0x88, 0x14, 0xB0], [0xA0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3C, 0x00],
+    [0x54, 0x5A, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7C, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0x4C, 0x9A, 0xEC], [0x78, 0x7C, 0xEC], [0xB0, 0x62, 0xEC],
+    [0xE4, 0x54, 0xEC], [0xEC, 0x58, 0xB4], [0xEC, 0x6A, 0x64], [0xD4, 0x88, 0x20],
+    [0xA0, 0xAA, 0x00], [0x74, 0xC4, 0x00], [0x4C, 0xD0, 0x20], [0x38, 0xCC, 0x6C],
+    [0x38, 0xB4, 0xCC], [0x3C, 0x3C, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0xA8, 0xCC, 0xEC], [0xBC, 0xBC, 0xEC], [0xD4, 0xB2, 0xEC],
+    [0xEC, 0xAE, 0xEC], [0xEC, 0xAE, 0xD4], [0xEC, 0xB4, 0xB0], [0xE4, 0xC4, 0x90],
+    [0xCC, 0xD2, 0x78], [0xB4, 0xDE, 0x78], [0xA8, 0xE2, 0x90], [0x98, 0xE2, 0xB4],
+    [0xA0, 0xD6, 0xE4], [0xA0, 0xA2, 0xA0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
+
+// Combinations of the color emphasis bits of PPUMASK
+pub const EMPHASES: usize = 8;
+
+// Each emphasis bit darkens the other two channels, so setting all of them darkens everything.
+// The ratio approximates what NTSC TVs show, for palettes without their own emphasized colors.
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+/*
+ * .pal files, as used by FCEUX and Nestopia, are raw RGB triplets.
+ * 192 bytes have 64 colors, and 1536 bytes have 64 colors for each combination of the emphasis bits,
+ * in the order of PPUMASK bits 5-7.
+ */
+const PAL_SIZE: usize = COLORS * 3;
+const PAL_WITH_EMPHASIS_SIZE: usize = PAL_SIZE * EMPHASES;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>, // 64 colors for each combination of the emphasis bits
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Palette {
+    // The built-in palette
+    pub fn new() -> Self {
+        Self {
+            colors: with_emphasis(&NTSC_PALETTE),
+        }
+    }
+
+    pub fn from_pal(data: &[u8]) -> Result<Self, String> {
+        if data.len() != PAL_SIZE && data.len() != PAL_WITH_EMPHASIS_SIZE {
+            return Err(format!(
+                "Palette must be {} or {} bytes, but {} bytes", PAL_SIZE, PAL_WITH_EMPHASIS_SIZE, data.len()));
+        }
+
+        let colors: Vec<[u8; 3]> = data.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        Ok(Self {
+            colors: if colors.len() == COLORS { with_emphasis(&colors) } else { colors },
+        })
+    }
+
+    // RGB of a 6-bit color index under the emphasis bits (PPUMASK >> 5).
+    // The upper 2 bits of the index are ignored.
+    pub fn rgb(&self, index: u8, emphasis: u8) -> [u8; 3] {
+        self.colors[(emphasis as usize % EMPHASES) * COLORS + index as usize % COLORS]
+    }
+
+    // RGB of a 9-bit pixel from the PPU: the emphasis bits in bits 6-8 and the color index in bits 0-5.
+    pub fn pixel_rgb(&self, pixel: u16) -> [u8; 3] {
+        self.rgb(pixel as u8 & 0x3F, (pixel >> 6) as u8)
+    }
+}
+
+// Derive the colors under each combination of the emphasis bits from the 64 colors without emphasis.
+// Bit 0 emphasizes red, 1 green and 2 blue.
+fn with_emphasis(colors: &[[u8; 3]]) -> Vec<[u8; 3]> {
+    (0..EMPHASES).flat_map(|emphasis| {
+        colors.iter().map(move |rgb| {
+            let mut rgb = *rgb;
+            for (channel, value) in rgb.iter_mut().enumerate() {
+                if emphasis & !(1 << channel) != 0 {
+                    *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                }
+            }
+            rgb
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NTSC_PALETTE;
+    use super::Palette;
+
+    #[test]
+    fn built_in() {
+        let palette = Palette::new();
+        assert_eq!(palette.rgb(0x21, 0), NTSC_PALETTE[0x21]);
+        assert_eq!(palette.rgb(0x61, 0), NTSC_PALETTE[0x21]);
+        assert_eq!(palette.rgb(0x20, 0), [236, 238, 236]);
+
+        // Emphasis darkens the other channels.
+        assert_eq!(palette.rgb(0x20, 0b001), [236, 194, 193]);
+        assert_eq!(palette.rgb(0x20, 0b100), [193, 194, 236]);
+        assert_eq!(palette.rgb(0x20, 0b111), [193, 194, 193]);
+        assert_eq!(palette.rgb(0x0F, 0b111), [0, 0, 0]);
+
+        assert_eq!(palette.pixel_rgb(0x020), palette.rgb(0x20, 0));
+        assert_eq!(palette.pixel_rgb(0x160), palette.rgb(0x20, 0b101));
+    }
+
+    #[test]
+    fn from_pal() {
+        let data: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.rgb(0x01, 0), [3, 4, 5]);
+        assert_eq!(palette.rgb(0x3F, 0), [189, 190, 191]);
+        assert_eq!(palette.rgb(0x3F, 0b010), [154, 190, 156]);
+
+        let data: Vec<u8> = (0..1536).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::from_pal(&data).unwrap();
+        assert_eq!(palette.rgb(0x01, 0), [0x01, 0x01, 0x01]);
+        assert_eq!(palette.rgb(0x01, 0b010), [0x81, 0x81, 0x81]);
+
+        assert!(Palette::from_pal(&[0; 191]).is_err());
+        assert!(Palette::from_pal(&[]).is_err());
+    }
+}
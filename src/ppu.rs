@@ -1,24 +1,41 @@
-use super::nes::Nes;
-use super::cassette::SPRITE_WIDTH;
-use super::cassette::SPRITE_HEIGHT;
-use super::ppu_register_bus::PpuDataStatus;
+use super::cassette::Cassette;
+use super::cassette::Mirroring;
+use super::ppu_register_bus::PpuRegisterBus;
+use super::ppu_register_bus::{STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_ZERO_HIT, STATUS_VBLANK};
 
-const VRAM_SIZE: usize = 0x0800;
+// 2 KiB in the console, and 2 KiB more on four-screen cartridges, which are kept here as well.
+const VRAM_SIZE: usize = 0x1000;
+const NAMETABLE_SIZE: usize = 0x0400;
 const OAM_SIZE: usize = 0x0100;
+const SPRITES_PER_SCANLINE: usize = 8;
+const PALETTE_RAM_SIZE: usize = 0x20;
 
 pub const VISIBLE_SCREEN_WIDTH: usize = 256;
 pub const VISIBLE_SCREEN_HEIGHT: usize = 240;
-const VISIBLE_SCREEN_SPRITES: usize = VISIBLE_SCREEN_WIDTH / SPRITE_WIDTH;
 
 // https://wiki.nesdev.com/w/index.php/PPU_rendering#Line-by-line_timing
 const CYCLES_PER_SCANLINE: usize = 341;
 const SCANLINES_PER_FRAME: usize = 262;
-const CYCLES_PER_FRAME: usize = CYCLES_PER_SCANLINE * SCANLINES_PER_FRAME;
+const VBLANK_SCANLINE: usize = 241;
+const PRE_RENDER_SCANLINE: usize = SCANLINES_PER_FRAME - 1;
 
-// 16ラインずつ処理
-const RENDERING_BATCH_SPRITES: usize = VISIBLE_SCREEN_SPRITES * 2;
-const RENDERING_BATCH_LINES: usize = RENDERING_BATCH_SPRITES / VISIBLE_SCREEN_SPRITES * SPRITE_HEIGHT;
-const RENDERING_BATCH_NUM: usize = VISIBLE_SCREEN_HEIGHT / RENDERING_BATCH_LINES;
+// Byte 2 of OAM entries
+// https://wiki.nesdev.com/w/index.php/PPU_OAM
+const SPRITE_PALETTE: u8 = 0b0000_0011;
+const SPRITE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTALLY: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICALLY: u8 = 0b1000_0000;
+
+// VRAM address (loopy v and t): 0yyy NNYY YYYX XXXX
+// fine Y, nametable, coarse Y and coarse X
+// https://wiki.nesdev.com/w/index.php/PPU_scrolling
+const COARSE_X: u16 = 0x001F;
+const COARSE_Y: u16 = 0x03E0;
+const NAMETABLE_X: u16 = 0x0400;
+const NAMETABLE_Y: u16 = 0x0800;
+const FINE_Y: u16 = 0x7000;
+const HORIZONTAL: u16 = NAMETABLE_X | COARSE_X;
+const VERTICAL: u16 = FINE_Y | NAMETABLE_Y | COARSE_Y;
 
 pub enum Register {
     PPUCTRL,
@@ -29,7 +46,6 @@ pub enum Register {
     PPUSCROLL,
     PPUADDR,
     PPUDATA,
-    OAMDMA,
 }
 
 impl From<Register> for usize {
@@ -43,7 +59,6 @@ impl From<Register> for usize {
             Register::PPUSCROLL => 0x2005,
             Register::PPUADDR => 0x2006,
             Register::PPUDATA => 0x2007,
-            Register::OAMDMA => 0x4014,
         }
     }
 }
@@ -59,20 +74,72 @@ impl From<u16> for Register {
             0x2005 => Register::PPUSCROLL,
             0x2006 => Register::PPUADDR,
             0x2007 => Register::PPUDATA,
-            0x4014 => Register::OAMDMA,
             _ => panic!("Invalid address for PPU Register: {:04X}", a),
         }
     }
 }
 
 
+// A sprite on the next scanline, found by sprite evaluation
+#[derive(Clone, Copy)]
+struct ScanlineSprite {
+    x: u8,
+    attributes: u8,
+    pattern_addr: u16, // The row of the pattern to fetch
+    pattern_low: u8, // Already flipped horizontally
+    pattern_high: u8,
+}
+
+impl ScanlineSprite {
+    fn new() -> Self {
+        Self {
+            x: 0,
+            attributes: 0,
+            pattern_addr: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+        }
+    }
+}
+
 pub struct Ppu {
     vram: [u8; VRAM_SIZE],
     oam: [u8; OAM_SIZE],
-    ppu_addr: u16,
-    cycle_counter: usize,
-    batch_counter: usize,
-    pub screen: [[[u8; 3]; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT],
+    palette_ram: [u8; PALETTE_RAM_SIZE],
+    // Current VRAM address (loopy v), used by both rendering and PPUDATA
+    vram_addr: u16,
+
+    scanline: usize,
+    dot: usize,
+    odd_frame: bool,
+    // PPUSTATUS was read right before VBlank starts, so the flag isn't set in this frame.
+    vblank_suppressed: bool,
+
+    // Background pipeline
+    // https://wiki.nesdev.com/w/index.php/PPU_rendering
+    nametable_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    pattern_low_shift: u16,
+    pattern_high_shift: u16,
+    attribute_latch: u8, // The 2-bit palette of the fetched tile
+    attribute_low_shift: u16,
+    attribute_high_shift: u16,
+
+    // Sprite pipeline
+    sprites: [ScanlineSprite; SPRITES_PER_SCANLINE],
+    sprite_count: usize,
+    sprite_zero_on_scanline: bool, // Whether sprites[0] is sprite 0 of OAM
+
+    // The picture as the PPU outputs it: 9-bit pixels of the emphasis bits (PPUMASK bits 5-7) and
+    // the 6-bit color index. Turning them into RGB is up to the TV, see Palette::pixel_rgb.
+    pub pixels: Box<[[u16; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT]>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Ppu {
@@ -80,40 +147,59 @@ impl Ppu {
         Self {
             vram: [0; VRAM_SIZE],
             oam: [0; OAM_SIZE],
-            ppu_addr: 0,
-            cycle_counter: 0,
-            batch_counter: 0,
-            screen: [[[0, 0, 0]; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT],
+            palette_ram: [0; PALETTE_RAM_SIZE],
+            vram_addr: 0,
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            vblank_suppressed: false,
+            nametable_latch: 0,
+            pattern_low_latch: 0,
+            pattern_high_latch: 0,
+            pattern_low_shift: 0,
+            pattern_high_shift: 0,
+            attribute_latch: 0,
+            attribute_low_shift: 0,
+            attribute_high_shift: 0,
+            sprites: [ScanlineSprite::new(); SPRITES_PER_SCANLINE],
+            sprite_count: 0,
+            sprite_zero_on_scanline: false,
+            pixels: Box::new([[0; VISIBLE_SCREEN_WIDTH]; VISIBLE_SCREEN_HEIGHT]),
         }
     }
 
-    pub fn step(&mut self, nes: &mut Nes, cpu_cycle: usize) -> bool {
-        self.cycle_counter += cpu_cycle * 3;
+    // Run the PPU for the given CPU cycles, 3 dots per cycle.
+    // Returns true when a frame has been completed, which is when VBlank starts.
+    pub fn step(&mut self, cassette: &Cassette, bus: &mut PpuRegisterBus, cpu_cycle: usize) -> bool {
+        let mut frame_completed = false;
+        for _ in 0..cpu_cycle * 3 {
+            frame_completed |= self.tick(cassette, bus);
+        }
+
+        frame_completed
+    }
 
-        self.handle_io(nes);
-        let rendered = self.render(nes);
+    pub fn oam(&self) -> &[u8] {
+        &self.oam
+    }
 
-        if self.cycle_counter >= CYCLES_PER_FRAME {
-            self.cycle_counter -= CYCLES_PER_FRAME;
-            self.batch_counter = 0;
-        }
+    pub fn scanline(&self) -> usize {
+        self.scanline
+    }
 
-        rendered
+    pub fn dot(&self) -> usize {
+        self.dot
     }
 
     // ref. https://wiki.nesdev.com/w/index.php/PPU_memory_map
-    fn read(&mut self, nes: &mut Nes, addr: u16) -> u8 {
+    fn read(&mut self, cassette: &Cassette, addr: u16) -> u8 {
         let addr = addr as usize;
         match addr {
-            0x0000..=0x1FFF => nes.read_chr_rom(addr as u16),
-            0x2000..=0x2FFF => {
-                self.vram[addr - 0x2000]
-            },
-            0x3000..=0x3EFF => { // mirrors of 0x2000 - 0x2eff
-                self.vram[addr - 0x3000]
+            0x0000..=0x1FFF => cassette.read_chr_rom(addr as u16),
+            0x2000..=0x3EFF => { // 0x3000 - 0x3EFF mirror 0x2000 - 0x2EFF
+                self.vram[Self::vram_index(cassette.mirroring(), addr)]
             },
-            0x3F00..=0x3F1F => { todo!("Palette RAM is not implemented") },
-            0x3F20..=0x3FFF => { todo!("Palette RAM is not implemented") },
+            0x3F00..=0x3FFF => self.palette_ram[Self::palette_index(addr)],
             _ => {
                 panic!("Out of PPU's addressing range: 0x{:X}", addr)
             },
@@ -121,84 +207,1116 @@ impl Ppu {
     }
 
     // ref. https://wiki.nesdev.com/w/index.php/PPU_memory_map
-    fn write(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, cassette: &Cassette, addr: u16, data: u8) {
         let addr = addr as usize;
         match addr {
             0x0000..=0x1FFF => {
                 //panic!("Write access is forbidden: PPU's 0x{:X}", addr),
             },
-            0x2000..=0x2FFF => {
-                self.vram[addr - 0x2000] = data;
+            0x2000..=0x3EFF => { // 0x3000 - 0x3EFF mirror 0x2000 - 0x2EFF
+                self.vram[Self::vram_index(cassette.mirroring(), addr)] = data;
             },
-            0x3000..=0x3EFF => { // mirrors of 0x2000 - 0x2eff
-                self.vram[addr - 0x3000] = data;
-            },
-            0x3F00..=0x3F1F => { todo!("Palette RAM is not implemented") },
-            0x3F20..=0x3FFF => { todo!("Palette RAM is not implemented") },
+            0x3F00..=0x3FFF => self.palette_ram[Self::palette_index(addr)] = data & 0x3F,
             _ => {
                 panic!("Out of PPU's addressing range: 0x{:X}", addr)
             },
         }
     }
 
-    fn increment_ppu_addr(&mut self) {
-        // TODO: Consider PPUCTRL (bit 2 of 0x2000)
-        self.ppu_addr = self.ppu_addr.wrapping_add(1);
+    // The cartridge decides which nametables share VRAM.
+    // https://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+    fn vram_index(mirroring: Mirroring, addr: usize) -> usize {
+        let addr = (addr - 0x2000) % (NAMETABLE_SIZE * 4);
+        let nametable = addr / NAMETABLE_SIZE;
+        let vram_nametable = match mirroring {
+            Mirroring::Horizontal => nametable / 2,
+            Mirroring::Vertical => nametable % 2,
+            Mirroring::SingleScreenA => 0,
+            Mirroring::SingleScreenB => 1,
+            Mirroring::FourScreen => nametable,
+        };
+        vram_nametable * NAMETABLE_SIZE + addr % NAMETABLE_SIZE
+    }
+
+    // 0x3F20 - 0x3FFF mirror 0x3F00 - 0x3F1F, and the backdrop colors of sprite palettes
+    // (0x3F10, 0x3F14, 0x3F18 and 0x3F1C) mirror those of background palettes.
+    // https://wiki.nesdev.com/w/index.php/PPU_palettes#Memory_Map
+    fn palette_index(addr: usize) -> usize {
+        let index = addr % PALETTE_RAM_SIZE;
+        if index & 0x13 == 0x10 {
+            index & 0x0F
+        } else {
+            index
+        }
+    }
+
+    // Rendering is enabled, and the PPU is on a line which fetches, so VRAM and OAM are busy.
+    fn rendering(&self, bus: &PpuRegisterBus) -> bool {
+        bus.rendering_enabled() && (self.scanline < VISIBLE_SCREEN_HEIGHT || self.scanline == PRE_RENDER_SCANLINE)
     }
 
-    fn handle_io(&mut self, nes: &mut Nes) {
-        if let Some(addr) = nes.ppu_register_bus.ppu_read(Register::PPUADDR) {
-            self.ppu_addr = addr;
+    // After an access to PPUDATA. While rendering, it bumps both coarse X and Y instead.
+    fn increment_vram_addr(&mut self, bus: &PpuRegisterBus) {
+        if self.rendering(bus) {
+            self.increment_coarse_x();
+            self.increment_y();
+        } else {
+            self.vram_addr = self.vram_addr.wrapping_add(bus.vram_increment()) & 0x7FFF;
+        }
+    }
+
+    // A read of the PPU registers by the CPU. It takes effect right away, with its side effects.
+    pub fn cpu_read(&mut self, cassette: &Cassette, bus: &mut PpuRegisterBus, addr: u16) -> u8 {
+        match addr.into() {
+            Register::PPUSTATUS => {
+                // https://wiki.nesdev.com/w/index.php/PPU_frame_timing#VBL_Flag_Timing
+                // Reading PPUSTATUS one dot before VBlank starts returns the flag cleared, and the flag is
+                // never set in that frame. A read just after the flag is set clears it before the CPU sees
+                // the NMI, which suppresses the NMI as well.
+                if (self.scanline, self.dot) == (VBLANK_SCANLINE, 1) {
+                    self.vblank_suppressed = true;
+                }
+                bus.cpu_read(addr)
+            },
+            Register::OAMDATA => {
+                // Bits 2-4 of the attribute byte don't exist in OAM and read back as 0.
+                let addr = bus.oam_addr() as usize;
+                let data = if addr & 0x03 == 2 { self.oam[addr] & 0xE3 } else { self.oam[addr] };
+                bus.drive_latch(data)
+            },
+            Register::PPUDATA => {
+                // The PPU address bus is 14 bits wide.
+                let addr = self.vram_addr & 0x3FFF;
+                let data = if addr >= 0x3F00 {
+                    // Palette reads return right away, and the buffer gets the nametable byte underneath.
+                    // Palette entries are 6 bits wide, and the upper 2 bits are stale bus contents.
+                    let buffer = self.read(cassette, addr - 0x1000);
+                    bus.swap_read_buffer(buffer);
+                    self.read(cassette, addr) | bus.latch() & 0xC0
+                } else {
+                    let buffer = self.read(cassette, addr);
+                    bus.swap_read_buffer(buffer)
+                };
+                self.increment_vram_addr(bus);
+                bus.drive_latch(data)
+            },
+            _ => bus.cpu_read(addr),
         }
+    }
 
-        match nes.ppu_register_bus.ppu_data_status() {
-            PpuDataStatus::Read => {
-                let data = self.read(nes, self.ppu_addr);
-                nes.ppu_register_bus.ppu_write(Register::PPUDATA, data);
-                debug!("PPU copied {:02X} into PPUDATA from VRAM[{:04X}]", data, self.ppu_addr);
-                self.increment_ppu_addr();
+    // A write to the PPU registers by the CPU. It takes effect right away, with its side effects.
+    pub fn cpu_write(&mut self, cassette: &Cassette, bus: &mut PpuRegisterBus, addr: u16, data: u8) {
+        match addr.into() {
+            Register::OAMDATA if self.rendering(bus) => {
+                // OAM is busy with sprite evaluation. The write is lost, and OAMADDR only moves to the
+                // next sprite.
+                bus.drive_latch(data);
+                bus.increment_oam_sprite();
             },
-            PpuDataStatus::Written => {
-                if let Some(data) = nes.ppu_register_bus.ppu_read(Register::PPUDATA) {
-                    self.write(self.ppu_addr, data as u8);
-                    debug!("PPU copied {:02X} from PPUDATA into VRAM[{:04X}]", data as u8, self.ppu_addr);
+            Register::OAMDATA => {
+                self.oam[bus.oam_addr() as usize] = data;
+                bus.cpu_write(addr, data);
+            },
+            Register::PPUADDR => {
+                // The second write copies t into v.
+                bus.cpu_write(addr, data);
+                if !bus.write_toggle() {
+                    self.vram_addr = bus.temp_addr();
                 }
-                self.increment_ppu_addr();
             },
-            PpuDataStatus::None => {},
+            Register::PPUDATA => {
+                let vram_addr = self.vram_addr & 0x3FFF;
+                self.write(cassette, vram_addr, data);
+                self.increment_vram_addr(bus);
+                bus.cpu_write(addr, data);
+            },
+            _ => bus.cpu_write(addr, data),
         }
     }
 
-    fn render(&mut self, nes: &mut Nes) -> bool {
-        if self.batch_counter >= RENDERING_BATCH_NUM {
-            return false
+    // One dot
+    fn tick(&mut self, cassette: &Cassette, bus: &mut PpuRegisterBus) -> bool {
+        let mut frame_completed = false;
+
+        match (self.scanline, self.dot) {
+            (0..=239, _) => self.render_dot(cassette, bus),
+            (VBLANK_SCANLINE, 1) => {
+                if !std::mem::replace(&mut self.vblank_suppressed, false) {
+                    bus.set_status(STATUS_VBLANK, true);
+                }
+                frame_completed = true;
+            },
+            (PRE_RENDER_SCANLINE, _) => {
+                if self.dot == 1 {
+                    bus.set_status(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW, false);
+                }
+                self.render_dot(cassette, bus);
+            },
+            _ => {},
         }
-        if self.cycle_counter < CYCLES_PER_SCANLINE * RENDERING_BATCH_LINES * (self.batch_counter + 1) {
-            return false
+
+        self.dot += 1;
+
+        // The pre-render line of odd frames is one dot shorter while rendering.
+        if self.scanline == PRE_RENDER_SCANLINE && self.dot == CYCLES_PER_SCANLINE - 1
+            && self.odd_frame && bus.rendering_enabled() {
+            self.dot += 1;
         }
 
-        self.render_batch_lines(nes);
-        self.batch_counter += 1;
+        if self.dot == CYCLES_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline == SCANLINES_PER_FRAME {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
 
-        true
+        frame_completed
     }
 
-    fn render_batch_lines(&mut self, nes: &mut Nes) {
-        const COLORS: [[u8; 3]; 4] = [[0, 0, 0], [63, 63, 63], [127, 127, 127], [255, 255, 255]];
+    // A dot of the visible scanlines and the pre-render scanline, which fetches for the next line.
+    // https://wiki.nesdev.com/w/images/4/4f/Ppu.svg
+    fn render_dot(&mut self, cassette: &Cassette, bus: &mut PpuRegisterBus) {
+        let dot = self.dot;
+
+        if !bus.rendering_enabled() {
+            if self.scanline != PRE_RENDER_SCANLINE && (1..=256).contains(&dot) {
+                self.put_pixel(0, bus);
+            }
+            return
+        }
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.pattern_low_shift <<= 1;
+            self.pattern_high_shift <<= 1;
+            self.attribute_low_shift <<= 1;
+            self.attribute_high_shift <<= 1;
+        }
 
-        let sprite_offset = self.batch_counter * RENDERING_BATCH_SPRITES;
-        for i in 0..RENDERING_BATCH_SPRITES {
-            let sprite_id = self.read(nes, (0x2000+sprite_offset+i) as u16);
-            let sprite = nes.get_sprite(sprite_id);
+        if self.scanline != PRE_RENDER_SCANLINE && (1..=256).contains(&dot) {
+            let background = self.background_pixel(bus);
+            if self.sprite_zero_hit(background, bus) {
+                bus.set_status(STATUS_SPRITE_ZERO_HIT, true);
+            }
+            let pixel = match self.sprite_pixel(bus) {
+                Some((sprite, behind)) if background == 0 || !behind => sprite,
+                _ => background,
+            };
+            self.put_pixel(pixel, bus);
+        }
+
+        // The tile fetched in the last 8 dots goes into the shift registers.
+        if dot % 8 == 1 && ((9..=257).contains(&dot) || dot == 329 || dot == 337) {
+            self.reload_shifters();
+        }
 
-            let offset_x = i % VISIBLE_SCREEN_SPRITES * SPRITE_WIDTH;
-            let offset_y = i / VISIBLE_SCREEN_SPRITES * SPRITE_HEIGHT + self.batch_counter * RENDERING_BATCH_LINES;
+        // Each tile takes 8 dots: nametable byte, attribute byte, and the low and high bytes of
+        // the pattern, 2 dots each.
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match dot % 8 {
+                1 => self.nametable_latch = self.read(cassette, 0x2000 | (self.vram_addr & 0x0FFF)),
+                3 => self.attribute_latch = self.fetch_attribute(cassette),
+                5 => self.pattern_low_latch = self.read(cassette, self.pattern_addr(bus)),
+                7 => self.pattern_high_latch = self.read(cassette, self.pattern_addr(bus) + 8),
+                0 => self.increment_coarse_x(),
+                _ => {},
+            }
+        }
 
-            for x in 0..SPRITE_WIDTH {
-                for y in 0..SPRITE_HEIGHT {
-                    self.screen[offset_y + y][offset_x + x] = COLORS[sprite.get(x, y) as usize];
+        // Each sprite takes 8 dots as well: garbage nametable and attribute bytes, and the pattern.
+        if (257..=320).contains(&dot) {
+            bus.reset_oam_addr();
+            let i = (dot - 257) / 8;
+            if i < self.sprite_count {
+                let addr = self.sprites[i].pattern_addr;
+                match (dot - 257) % 8 {
+                    4 => self.sprites[i].pattern_low = self.fetch_sprite_pattern(cassette, i, addr),
+                    6 => self.sprites[i].pattern_high = self.fetch_sprite_pattern(cassette, i, addr + 8),
+                    _ => {},
                 }
             }
         }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => {
+                self.vram_addr = (self.vram_addr & !HORIZONTAL) | (bus.temp_addr() & HORIZONTAL);
+                self.evaluate_sprites(bus);
+            },
+            280..=304 if self.scanline == PRE_RENDER_SCANLINE => {
+                self.vram_addr = (self.vram_addr & !VERTICAL) | (bus.temp_addr() & VERTICAL);
+            },
+            _ => {},
+        }
+    }
+
+    fn pattern_addr(&self, bus: &PpuRegisterBus) -> u16 {
+        let fine_y = (self.vram_addr & FINE_Y) >> 12;
+        bus.background_pattern_table_addr() + self.nametable_latch as u16 * 16 + fine_y
+    }
+
+    // Put the fetched tile into the lower bytes of the shift registers.
+    fn reload_shifters(&mut self) {
+        self.pattern_low_shift = (self.pattern_low_shift & 0xFF00) | self.pattern_low_latch as u16;
+        self.pattern_high_shift = (self.pattern_high_shift & 0xFF00) | self.pattern_high_latch as u16;
+        // Every pixel of a tile has the same palette.
+        let low = if self.attribute_latch & 0b01 == 0 { 0x00 } else { 0xFF };
+        let high = if self.attribute_latch & 0b10 == 0 { 0x00 } else { 0xFF };
+        self.attribute_low_shift = (self.attribute_low_shift & 0xFF00) | low;
+        self.attribute_high_shift = (self.attribute_high_shift & 0xFF00) | high;
+    }
+
+    // An attribute byte has the palettes of 4 areas of 16x16 pixels in a 32x32 area:
+    // bits 0-1 top left, 2-3 top right, 4-5 bottom left and 6-7 bottom right.
+    // https://wiki.nesdev.com/w/index.php/PPU_attribute_tables
+    fn fetch_attribute(&mut self, cassette: &Cassette) -> u8 {
+        let v = self.vram_addr;
+        let addr = 0x23C0 | (v & (NAMETABLE_X | NAMETABLE_Y)) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+        // Bit 1 of coarse X selects right, and bit 1 of coarse Y selects bottom.
+        let shift = ((v >> 4) & 0x04) | (v & 0x02);
+        (self.read(cassette, addr) >> shift) & 0x03
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if self.vram_addr & COARSE_X == COARSE_X {
+            self.vram_addr &= !COARSE_X;
+            self.vram_addr ^= NAMETABLE_X;
+        } else {
+            self.vram_addr += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.vram_addr & FINE_Y != FINE_Y {
+            self.vram_addr += 0x1000;
+            return
+        }
+
+        self.vram_addr &= !FINE_Y;
+        let coarse_y = (self.vram_addr & COARSE_Y) >> 5;
+        let coarse_y = match coarse_y {
+            // The last row of the nametable. Rows 30 and 31 are attributes.
+            29 => {
+                self.vram_addr ^= NAMETABLE_Y;
+                0
+            },
+            31 => 0,
+            _ => coarse_y + 1,
+        };
+        self.vram_addr = (self.vram_addr & !COARSE_Y) | (coarse_y << 5);
+    }
+
+    // Find the first 8 sprites on the next scanline. No sprites are on the first scanline.
+    // The hardware does this over dots 65-256, and sets the overflow flag somewhere in them depending on
+    // when it finds the 9th sprite. Here it all happens at dot 257, so a PPUSTATUS read late in the line
+    // can miss the flag which the hardware would have set.
+    // https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation
+    fn evaluate_sprites(&mut self, bus: &mut PpuRegisterBus) {
+        self.sprite_count = 0;
+        self.sprite_zero_on_scanline = false;
+        if self.scanline == PRE_RENDER_SCANLINE {
+            return
+        }
+
+        let height = bus.sprite_height();
+        // Sprites are drawn one line below their Y coordinates.
+        let scanline = self.scanline;
+        let on_next_scanline = |y: u8| scanline.wrapping_sub(y as usize) < height;
+
+        let mut n = 0;
+        let mut m = 0;
+        while n < OAM_SIZE / 4 {
+            if self.sprite_count < SPRITES_PER_SCANLINE {
+                if on_next_scanline(self.oam[n * 4]) {
+                    self.add_scanline_sprite(n, bus);
+                }
+                n += 1;
+                continue
+            }
+
+            // After 8 sprites are found, the hardware looks for more with a bug. It increments the
+            // byte offset as well as the sprite index, so it checks tile numbers, attributes and X
+            // coordinates as if they were Y coordinates. This misses some sprites and finds false ones.
+            if on_next_scanline(self.oam[n * 4 + m]) {
+                bus.set_status(STATUS_SPRITE_OVERFLOW, true);
+                break
+            }
+            n += 1;
+            m = (m + 1) % 4;
+        }
+    }
+
+    fn add_scanline_sprite(&mut self, n: usize, bus: &PpuRegisterBus) {
+        let height = bus.sprite_height();
+        let entry = &self.oam[n * 4..n * 4 + 4];
+        let row = self.scanline - entry[0] as usize;
+
+        let (tile, attributes) = (entry[1] as u16, entry[2]);
+        let row = if attributes & SPRITE_FLIP_VERTICALLY == 0 { row } else { height - 1 - row } as u16;
+        // 8x16 sprites take the pattern table from bit 0 of the tile number, and consist of 2 tiles.
+        let pattern_addr = if height == 8 {
+            bus.sprite_pattern_table_addr() + tile * 16 + row
+        } else {
+            (tile & 0x01) * 0x1000 + (tile & 0xFE) * 16 + (row & 0x08) * 2 + (row & 0x07)
+        };
+
+        self.sprites[self.sprite_count] = ScanlineSprite {
+            x: entry[3],
+            attributes,
+            pattern_addr,
+            pattern_low: 0,
+            pattern_high: 0,
+        };
+        self.sprite_count += 1;
+        if n == 0 {
+            self.sprite_zero_on_scanline = true;
+        }
+    }
+
+    fn fetch_sprite_pattern(&mut self, cassette: &Cassette, i: usize, addr: u16) -> u8 {
+        let pattern = self.read(cassette, addr);
+        if self.sprites[i].attributes & SPRITE_FLIP_HORIZONTALLY == 0 {
+            pattern
+        } else {
+            pattern.reverse_bits()
+        }
+    }
+
+    // Palette RAM index of the sprite at the current dot, and whether it's behind the background.
+    // Sprites earlier in OAM are in front of later ones, even when they are behind the background.
+    fn sprite_pixel(&self, bus: &PpuRegisterBus) -> Option<(u8, bool)> {
+        let x = self.dot - 1;
+        if !bus.show_sprites() || (x < 8 && !bus.show_sprites_left()) {
+            return None
+        }
+
+        self.sprites[..self.sprite_count].iter().find_map(|sprite| {
+            let color = Self::sprite_color(sprite, x);
+            if color == 0 {
+                return None
+            }
+
+            let palette = sprite.attributes & SPRITE_PALETTE;
+            Some((0x10 | palette << 2 | color, sprite.attributes & SPRITE_BEHIND_BACKGROUND != 0))
+        })
+    }
+
+    // 2-bit color of the sprite at X. 0 means transparent or outside.
+    fn sprite_color(sprite: &ScanlineSprite, x: usize) -> u8 {
+        let column = x.wrapping_sub(sprite.x as usize);
+        if column >= 8 {
+            return 0
+        }
+
+        let bit = 7 - column;
+        ((sprite.pattern_high >> bit) & 1) << 1 | ((sprite.pattern_low >> bit) & 1)
+    }
+
+    // Sprite 0 hits when its opaque pixel overlaps an opaque background pixel, even when it's
+    // behind the background or other sprites. It never hits at X=255, or where either of them is hidden.
+    // https://wiki.nesdev.com/w/index.php/PPU_OAM#Sprite_zero_hits
+    fn sprite_zero_hit(&self, background: u8, bus: &PpuRegisterBus) -> bool {
+        let x = self.dot - 1;
+        self.sprite_zero_on_scanline
+            && background != 0
+            && bus.show_sprites()
+            && x != 255
+            && (x >= 8 || bus.show_sprites_left())
+            && Self::sprite_color(&self.sprites[0], x) != 0
+    }
+
+    // Palette RAM index of the background at the current dot, selected by fine X scroll.
+    // 0 means transparent.
+    fn background_pixel(&self, bus: &PpuRegisterBus) -> u8 {
+        let x = self.dot - 1;
+        if !bus.show_background() || (x < 8 && !bus.show_background_left()) {
+            return 0
+        }
+
+        let bit = 15 - bus.fine_x();
+        let low = (self.pattern_low_shift >> bit) & 1;
+        let high = (self.pattern_high_shift >> bit) & 1;
+        if high << 1 | low == 0 {
+            return 0
+        }
+
+        let palette = ((self.attribute_high_shift >> bit) & 1) << 1 | ((self.attribute_low_shift >> bit) & 1);
+        (palette << 2 | high << 1 | low) as u8
+    }
+
+    fn put_pixel(&mut self, pixel: u8, bus: &PpuRegisterBus) {
+        // Transparent pixels show the backdrop color at 0x3F00.
+        let mut color = self.palette_ram[Self::palette_index(pixel as usize)];
+        // Grayscale keeps only the brightness, the column 0 of the palette.
+        if bus.grayscale() {
+            color &= 0x30;
+        }
+        self.pixels[self.scanline][self.dot - 1] = (bus.emphasis() as u16) << 6 | color as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ppu;
+    use super::Mirroring;
+    use super::CYCLES_PER_SCANLINE;
+    use super::OAM_SIZE;
+    use super::SCANLINES_PER_FRAME;
+    use super::VBLANK_SCANLINE;
+    use crate::cassette::Cassette;
+    use crate::ppu_register_bus::PpuRegisterBus;
+    use crate::ppu_register_bus::STATUS_SPRITE_OVERFLOW;
+    use crate::ppu_register_bus::STATUS_SPRITE_ZERO_HIT;
+    use crate::ppu_register_bus::STATUS_VBLANK;
+
+    // NROM with 16 KiB PRG ROM, 8 KiB CHR ROM and vertical mirroring
+    fn new_test_cassette() -> Cassette {
+        new_test_cassette_with_chr(&[])
+    }
+
+    fn new_test_cassette_with_chr(chr_rom: &[u8]) -> Cassette {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        data.resize(16 + 0x4000, 0);
+        data.extend_from_slice(chr_rom);
+        data.resize(16 + 0x4000 + 0x2000, 0);
+        Cassette::new(data)
+    }
+
+    // Tile 1: columns 0 and 7 are color 1. Tile 2: color 3 all over. Tile 3: color 1 all over.
+    fn new_background_test() -> (Ppu, Cassette, PpuRegisterBus) {
+        let mut chr_rom = vec![0; 0x40];
+        chr_rom[0x10..0x18].copy_from_slice(&[0x81; 8]);
+        chr_rom[0x20..0x30].copy_from_slice(&[0xFF; 16]);
+        chr_rom[0x30..0x38].copy_from_slice(&[0xFF; 8]);
+        let cassette = new_test_cassette_with_chr(&chr_rom);
+
+        let mut ppu = Ppu::new();
+        ppu.write(&cassette, 0x3F00, 0x0F);
+        ppu.write(&cassette, 0x3F01, 0x30);
+        ppu.write(&cassette, 0x3F03, 0x16);
+        for nametable in [0x2000, 0x2400].iter() {
+            for i in 0..0x3C0 {
+                ppu.write(&cassette, nametable + i, if i % 32 == 0 { 1 } else { 2 });
+            }
+        }
+
+        let mut bus = PpuRegisterBus::new();
+        bus.cpu_write(0x2001, 0x0A); // Show background including the leftmost 8 pixels
+
+        (ppu, cassette, bus)
+    }
+
+    // Tile 3: only the top left pixel is color 1. Tiles $104-$105: an 8x16 sprite whose 9th row is color 1.
+    // Background is disabled.
+    fn new_sprite_test() -> (Ppu, Cassette, PpuRegisterBus) {
+        let mut chr_rom = vec![0; 0x1060];
+        chr_rom[0x30] = 0x80;
+        chr_rom[0x1050] = 0xFF;
+        let cassette = new_test_cassette_with_chr(&chr_rom);
+
+        let mut ppu = Ppu::new();
+        ppu.write(&cassette, 0x3F00, 0x0F);
+        ppu.write(&cassette, 0x3F11, 0x30);
+        ppu.write(&cassette, 0x3F15, 0x16);
+        // Hide all sprites below the screen.
+        ppu.oam = [0xFF; OAM_SIZE];
+
+        let mut bus = PpuRegisterBus::new();
+        bus.cpu_write(0x2001, 0x14); // Show sprites including the leftmost 8 pixels
+
+        (ppu, cassette, bus)
+    }
+
+    fn run_frame(ppu: &mut Ppu, cassette: &Cassette, bus: &mut PpuRegisterBus) {
+        while !ppu.step(cassette, bus, 1) {}
+    }
+
+    fn run_until(ppu: &mut Ppu, cassette: &Cassette, bus: &mut PpuRegisterBus, scanline: usize) {
+        while ppu.scanline() != scanline {
+            ppu.step(cassette, bus, 1);
+        }
+    }
+
+    #[test]
+    fn palette_ram() {
+        let cassette = new_test_cassette();
+        let mut ppu = Ppu::new();
+
+        for i in 0..0x20 {
+            ppu.write(&cassette, 0x3F00 + i, i as u8);
+        }
+        assert_eq!(ppu.read(&cassette, 0x3F01), 0x01);
+        assert_eq!(ppu.read(&cassette, 0x3F11), 0x11);
+        // Backdrop colors of sprite palettes are shared with background palettes.
+        assert_eq!(ppu.read(&cassette, 0x3F00), 0x10);
+        assert_eq!(ppu.read(&cassette, 0x3F04), 0x14);
+        assert_eq!(ppu.read(&cassette, 0x3F18), 0x18);
+        assert_eq!(ppu.read(&cassette, 0x3F0C), 0x1C);
+        // Mirrors every 32 bytes
+        assert_eq!(ppu.read(&cassette, 0x3F25), 0x05);
+        assert_eq!(ppu.read(&cassette, 0x3FFF), 0x1F);
+
+        // Palette entries are 6 bits wide.
+        ppu.write(&cassette, 0x3FE3, 0xFF);
+        assert_eq!(ppu.read(&cassette, 0x3F03), 0x3F);
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut cassette = new_test_cassette();
+        let mut ppu = Ppu::new();
+        let nametables = [0x2000, 0x2400, 0x2800, 0x2C00];
+        let cases = [
+            (Mirroring::Horizontal, [0, 0, 1, 1]),
+            (Mirroring::Vertical, [0, 1, 0, 1]),
+            (Mirroring::SingleScreenA, [0, 0, 0, 0]),
+            (Mirroring::SingleScreenB, [1, 1, 1, 1]),
+            (Mirroring::FourScreen, [0, 1, 2, 3]),
+        ];
+        assert_eq!(cassette.mirroring(), Mirroring::Vertical);
+
+        for (mirroring, vram_nametables) in cases.iter() {
+            cassette.set_mirroring(*mirroring);
+            for (i, nametable) in nametables.iter().enumerate() {
+                ppu.write(&cassette, nametable + 0x123, i as u8);
+            }
+            for (nametable, vram_nametable) in nametables.iter().zip(vram_nametables.iter()) {
+                // The last write to the shared VRAM wins. 0x3000-0x3EFF mirror 0x2000-0x2EFF.
+                let last = vram_nametables.iter().rposition(|n| n == vram_nametable).unwrap() as u8;
+                assert_eq!(ppu.read(&cassette, nametable + 0x123), last, "{:?} {:04X}", mirroring, nametable);
+                assert_eq!(ppu.read(&cassette, nametable + 0x1123), last, "{:?} {:04X}", mirroring, nametable);
+            }
+        }
+    }
+
+    #[test]
+    fn ppu_data() {
+        let cassette = new_test_cassette_with_chr(&[0xAB, 0xCD]);
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+        // Accesses take effect immediately, even without the PPU stepping in between.
+        let write = |ppu: &mut Ppu, bus: &mut PpuRegisterBus, addr: u16, data: &[u8]| {
+            ppu.cpu_write(&cassette, bus, 0x2006, (addr >> 8) as u8);
+            ppu.cpu_write(&cassette, bus, 0x2006, addr as u8);
+            for d in data {
+                ppu.cpu_write(&cassette, bus, 0x2007, *d);
+            }
+        };
+        write(&mut ppu, &mut bus, 0x2000, &[0x11, 0x22]);
+        write(&mut ppu, &mut bus, 0x2FE0, &[0x33]);
+        write(&mut ppu, &mut bus, 0x3F00, &[0x0F]);
+
+        let read = |ppu: &mut Ppu, bus: &mut PpuRegisterBus, addr: u16, len: usize| {
+            write(ppu, bus, addr, &[]);
+            (0..len).map(|_| ppu.cpu_read(&cassette, bus, 0x2007)).collect::<Vec<u8>>()
+        };
+        // Reads are a byte behind.
+        assert_eq!(read(&mut ppu, &mut bus, 0x2000, 3), vec![0x00, 0x11, 0x22]);
+        assert_eq!(read(&mut ppu, &mut bus, 0x0000, 3), vec![0x00, 0xAB, 0xCD]);
+        // Palette reads are immediate, and the buffer gets the nametable byte at $2FE0.
+        // The upper 2 bits are stale bus contents. $3FE0 mirrors $3F00.
+        ppu.cpu_write(&cassette, &mut bus, 0x2006, 0x3F);
+        ppu.cpu_write(&cassette, &mut bus, 0x2006, 0xE0);
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2007), 0xCF);
+        assert_eq!(read(&mut ppu, &mut bus, 0x2000, 2), vec![0x33, 0x11]);
+
+        // PPUCTRL selects the increment.
+        ppu.cpu_write(&cassette, &mut bus, 0x2000, 0x04);
+        write(&mut ppu, &mut bus, 0x2001, &[0x44, 0x55]);
+        assert_eq!(ppu.read(&cassette, 0x2001), 0x44);
+        assert_eq!(ppu.read(&cassette, 0x2021), 0x55);
+    }
+
+    #[test]
+    fn oam_data() {
+        let cassette = new_test_cassette();
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0xFF);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0x12);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0x34);
+        assert_eq!(ppu.oam()[0xFF], 0x12);
+        assert_eq!(ppu.oam()[0x00], 0x34);
+
+        // Reads don't increment OAMADDR.
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x00);
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2004), 0x34);
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2004), 0x34);
+
+        // Unimplemented bits of attribute bytes read as 0.
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x06);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xFF);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xFF);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x06);
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2004), 0xE3);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x07);
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2004), 0xFF);
+    }
+
+    #[test]
+    fn oam_data_while_rendering() {
+        let cassette = new_test_cassette();
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+
+        bus.cpu_write(0x2001, 0x10);
+        run_until(&mut ppu, &cassette, &mut bus, 10);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x05);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xAB);
+        assert_eq!(ppu.oam()[0x05], 0x00);
+        assert_eq!(bus.oam_addr(), 0x09);
+        assert_eq!(bus.latch(), 0xAB);
+
+        // The pre-render line is busy as well.
+        run_until(&mut ppu, &cassette, &mut bus, 261);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0xFD);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xAB);
+        assert_eq!(ppu.oam()[0xFD], 0x00);
+        assert_eq!(bus.oam_addr(), 0x01);
+
+        // During VBlank, or with rendering disabled, OAM takes writes.
+        run_until(&mut ppu, &cassette, &mut bus, 241);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x05);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xAB);
+        assert_eq!(ppu.oam()[0x05], 0xAB);
+        run_until(&mut ppu, &cassette, &mut bus, 10);
+        bus.cpu_write(0x2001, 0x00);
+        ppu.cpu_write(&cassette, &mut bus, 0x2003, 0x06);
+        ppu.cpu_write(&cassette, &mut bus, 0x2004, 0xCD);
+        assert_eq!(ppu.oam()[0x06], 0xCD);
+    }
+
+    #[test]
+    fn oam_addr_reset_while_rendering() {
+        let cassette = new_test_cassette();
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+
+        bus.cpu_write(0x2003, 0x12);
+        run_until(&mut ppu, &cassette, &mut bus, 1);
+        assert_eq!(bus.oam_addr(), 0x12);
+
+        bus.cpu_write(0x2001, 0x10);
+        while ppu.dot() < 257 {
+            ppu.tick(&cassette, &mut bus);
+        }
+        assert_eq!(bus.oam_addr(), 0x12);
+        ppu.tick(&cassette, &mut bus);
+        assert_eq!(bus.oam_addr(), 0x00);
+
+        // Sprite tile loading of the pre-render line clears it as well.
+        run_until(&mut ppu, &cassette, &mut bus, 241);
+        bus.cpu_write(0x2003, 0x34);
+        run_until(&mut ppu, &cassette, &mut bus, 0);
+        assert_eq!(bus.oam_addr(), 0x00);
+    }
+
+    #[test]
+    fn frame_timing() {
+        let cassette = new_test_cassette();
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.scanline(), 241);
+        assert!(ppu.dot() >= 2 && ppu.dot() <= 4);
+        assert_eq!(bus.status() & STATUS_VBLANK, STATUS_VBLANK);
+
+        run_until(&mut ppu, &cassette, &mut bus, 261);
+        while ppu.dot() < 2 {
+            ppu.step(&cassette, &mut bus, 1);
+        }
+        assert_eq!(bus.status() & STATUS_VBLANK, 0);
+
+        // Rendering skips a dot in odd frames.
+        let mut dots = [0; 2];
+        bus.cpu_write(0x2001, 0x08);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        for dots in dots.iter_mut() {
+            while !ppu.tick(&cassette, &mut bus) {
+                *dots += 1;
+            }
+            *dots += 1;
+        }
+        dots.sort();
+        assert_eq!(dots, [CYCLES_PER_SCANLINE * SCANLINES_PER_FRAME - 1, CYCLES_PER_SCANLINE * SCANLINES_PER_FRAME]);
+    }
+
+    #[test]
+    fn vblank_suppression() {
+        let cassette = new_test_cassette();
+        let mut bus = PpuRegisterBus::new();
+        let mut ppu = Ppu::new();
+
+        // Reading PPUSTATUS right before the flag is set
+        while (ppu.scanline(), ppu.dot()) != (VBLANK_SCANLINE, 1) {
+            ppu.tick(&cassette, &mut bus);
+        }
+        assert_eq!(ppu.cpu_read(&cassette, &mut bus, 0x2002) & STATUS_VBLANK, 0);
+        ppu.step(&cassette, &mut bus, 1);
+        assert_eq!(bus.status() & STATUS_VBLANK, 0);
+
+        // Only for that frame
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(bus.status() & STATUS_VBLANK, STATUS_VBLANK);
+
+        // Reading earlier doesn't affect the flag.
+        while (ppu.scanline(), ppu.dot()) != (VBLANK_SCANLINE, 0) {
+            ppu.tick(&cassette, &mut bus);
+        }
+        ppu.cpu_read(&cassette, &mut bus, 0x2002);
+        ppu.step(&cassette, &mut bus, 1);
+        assert_eq!(bus.status() & STATUS_VBLANK, STATUS_VBLANK);
+    }
+
+    #[test]
+    fn background() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+
+        // The first frame after power-up has no tiles prefetched for the first line.
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        for y in [0, 100, 239].iter() {
+            assert_eq!(ppu.pixels[*y][0], 0x30);
+            assert_eq!(ppu.pixels[*y][1], 0x0F);
+            assert_eq!(ppu.pixels[*y][7], 0x30);
+            assert_eq!(ppu.pixels[*y][8], 0x16);
+            assert_eq!(ppu.pixels[*y][255], 0x16);
+        }
+
+        // Leftmost 8 pixels are hidden by PPUMASK.
+        bus.cpu_write(0x2001, 0x08);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][0], 0x0F);
+        assert_eq!(ppu.pixels[0][8], 0x16);
+
+        // Rendering disabled shows the backdrop color.
+        bus.cpu_write(0x2001, 0x00);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][8], 0x0F);
+    }
+
+    #[test]
+    fn mirroring_change_mid_frame() {
+        let (mut ppu, mut cassette, mut bus) = new_background_test();
+        for i in 0..0x3C0 {
+            ppu.write(&cassette, 0x2400 + i, 3);
+        }
+        bus.cpu_write(0x2000, 0x02); // Base nametable $2800
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_until(&mut ppu, &cassette, &mut bus, 120);
+        // $2800 now shares VRAM with $2400 instead of $2000.
+        cassette.set_mirroring(Mirroring::Horizontal);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][8], 0x16);
+        assert_eq!(ppu.pixels[119][8], 0x16);
+        assert_eq!(ppu.pixels[121][8], 0x30);
+        assert_eq!(ppu.pixels[239][8], 0x30);
+    }
+
+    #[test]
+    fn emphasis_and_grayscale() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        bus.cpu_write(0x2001, 0x2A); // Red emphasis
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][0], 0x070);
+        assert_eq!(ppu.pixels[0][8], 0x056);
+
+        bus.cpu_write(0x2001, 0xEB); // All emphasis bits and grayscale
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][0], 0x1F0);
+        assert_eq!(ppu.pixels[0][1], 0x1C0);
+        assert_eq!(ppu.pixels[0][8], 0x1D0);
+    }
+
+    #[test]
+    fn scroll() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+
+        // Fine X
+        bus.cpu_write(0x2005, 1);
+        bus.cpu_write(0x2005, 0);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][0], 0x0F);
+        assert_eq!(ppu.pixels[0][6], 0x30);
+        assert_eq!(ppu.pixels[0][7], 0x16);
+
+        // Coarse X, changed in the middle of the frame. It takes effect from the next line.
+        run_until(&mut ppu, &cassette, &mut bus, 100);
+        bus.cpu_write(0x2005, 8);
+        bus.cpu_write(0x2005, 0);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[100][7], 0x30);
+        assert_eq!(ppu.pixels[101][7], 0x16);
+        // Column 0 of the next nametable comes in from the right.
+        assert_eq!(ppu.pixels[101][248], 0x30);
+        assert_eq!(ppu.pixels[101][249], 0x0F);
+    }
+
+    #[test]
+    fn vertical_scroll() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+        // Tile 2 on even rows, and tile 0 on odd rows
+        for i in 0..0x3C0 {
+            ppu.write(&cassette, 0x2000 + i, if i / 32 % 2 == 0 { 2 } else { 0 });
+        }
+
+        bus.cpu_write(0x2005, 0);
+        bus.cpu_write(0x2005, 12);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][8], 0x0F);
+        assert_eq!(ppu.pixels[3][8], 0x0F);
+        assert_eq!(ppu.pixels[4][8], 0x16);
+        assert_eq!(ppu.pixels[11][8], 0x16);
+        assert_eq!(ppu.pixels[12][8], 0x0F);
+
+        // Writing PPUADDR in the middle of the frame moves the rendering address immediately,
+        // which is how status bars are split. v = 0: the first nametable, row 0 and fine Y 0
+        run_until(&mut ppu, &cassette, &mut bus, 100);
+        while ppu.dot() < 300 {
+            ppu.step(&cassette, &mut bus, 1);
+        }
+        ppu.cpu_write(&cassette, &mut bus, 0x2006, 0x00);
+        ppu.cpu_write(&cassette, &mut bus, 0x2006, 0x00);
+        ppu.step(&cassette, &mut bus, 1);
+        // The change of PPUSCROLL only affects the next frame.
+        bus.cpu_write(0x2005, 0);
+        bus.cpu_write(0x2005, 0);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[100][8], 0x16);
+        assert_eq!(ppu.pixels[101][8], 0x16);
+        assert_eq!(ppu.pixels[108][8], 0x16);
+        assert_eq!(ppu.pixels[109][8], 0x0F);
+    }
+
+    #[test]
+    fn attribute_table() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+        ppu.write(&cassette, 0x3F07, 0x21);
+        ppu.write(&cassette, 0x3F0B, 0x2A);
+        ppu.write(&cassette, 0x3F0F, 0x11);
+        // The top left 32x32 pixels: top right 16x16 is palette 1, bottom left 2 and bottom right 3.
+        ppu.write(&cassette, 0x23C0, 0b11_10_01_00);
+        // The 32x32 pixels right below: palette 1 all over
+        ppu.write(&cassette, 0x23C8, 0b01_01_01_01);
+        // The second nametable: palette 2 at the top left
+        ppu.write(&cassette, 0x27C0, 0b00_00_00_10);
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][8], 0x16);
+        assert_eq!(ppu.pixels[15][15], 0x16);
+        assert_eq!(ppu.pixels[0][16], 0x21);
+        assert_eq!(ppu.pixels[16][8], 0x2A);
+        assert_eq!(ppu.pixels[31][31], 0x11);
+        assert_eq!(ppu.pixels[32][8], 0x21);
+        assert_eq!(ppu.pixels[32][32], 0x16);
+        // The backdrop color doesn't depend on the palette.
+        assert_eq!(ppu.pixels[16][1], 0x0F);
+
+        // Attribute bytes follow fine X scroll.
+        bus.cpu_write(0x2005, 4);
+        bus.cpu_write(0x2005, 0);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][11], 0x16);
+        assert_eq!(ppu.pixels[0][12], 0x21);
+
+        // The attribute table of the next nametable
+        bus.cpu_write(0x2000, 0x01);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[0][4], 0x2A);
+        assert_eq!(ppu.pixels[0][12], 0x16);
+    }
+
+    #[test]
+    fn sprites() {
+        let (mut ppu, cassette, mut bus) = new_sprite_test();
+        let backdrop = 0x0F;
+        let color = 0x30;
+
+        // Y, tile, attributes, X
+        ppu.oam[0..16].copy_from_slice(&[
+            9, 3, 0x00, 16,
+            9, 3, 0x41, 32, // Flipped horizontally, palette 1
+            19, 3, 0x80, 48, // Flipped vertically
+            19, 3, 0xC0, 0, // Flipped both ways
+        ]);
+        run_frame(&mut ppu, &cassette, &mut bus);
+
+        assert_eq!(ppu.pixels[10][16], color);
+        assert_eq!(ppu.pixels[9][16], backdrop);
+        assert_eq!(ppu.pixels[11][16], backdrop);
+        assert_eq!(ppu.pixels[10][17], backdrop);
+
+        assert_eq!(ppu.pixels[10][32], backdrop);
+        assert_eq!(ppu.pixels[10][39], 0x16);
+
+        assert_eq!(ppu.pixels[20][48], backdrop);
+        assert_eq!(ppu.pixels[27][48], color);
+
+        assert_eq!(ppu.pixels[27][7], color);
+
+        // Leftmost 8 pixels are hidden by PPUMASK.
+        bus.cpu_write(0x2001, 0x10);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(ppu.pixels[27][7], backdrop);
+        assert_eq!(ppu.pixels[10][16], color);
+    }
+
+    #[test]
+    fn sprites_per_scanline() {
+        let (mut ppu, cassette, mut bus) = new_sprite_test();
+
+        for i in 0..9 {
+            ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[99, 3, 0x00, i as u8 * 8]);
+        }
+        // The 10th is below them.
+        ppu.oam[36..40].copy_from_slice(&[107, 3, 0x00, 72]);
+        run_frame(&mut ppu, &cassette, &mut bus);
+
+        for i in 0..8 {
+            assert_eq!(ppu.pixels[100][i * 8], 0x30);
+        }
+        assert_eq!(ppu.pixels[100][64], 0x0F);
+        assert_eq!(ppu.pixels[107][72], 0x0F);
+        assert_eq!(ppu.pixels[108][72], 0x30);
+    }
+
+    #[test]
+    fn sprite_priority() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+        ppu.write(&cassette, 0x3F11, 0x2A);
+        ppu.write(&cassette, 0x3F15, 0x21);
+        ppu.oam = [0xFF; OAM_SIZE];
+        ppu.oam[0..12].copy_from_slice(&[
+            9, 3, 0x20, 0, // Behind the background
+            9, 3, 0x01, 4, // In front of the background, but behind the first sprite
+            19, 3, 0x00, 8,
+        ]);
+        bus.cpu_write(0x2001, 0x1E);
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        // The background is opaque at columns 0 and 7 of the tiles at the left edge.
+        assert_eq!(ppu.pixels[10][0], 0x30);
+        assert_eq!(ppu.pixels[10][1], 0x2A);
+        assert_eq!(ppu.pixels[10][5], 0x2A);
+        // The first sprite hides the second one even where it's behind the background.
+        assert_eq!(ppu.pixels[10][7], 0x30);
+        assert_eq!(ppu.pixels[10][8], 0x21);
+        assert_eq!(ppu.pixels[20][8], 0x2A);
+        assert_eq!(ppu.pixels[20][16], 0x16);
+    }
+
+    #[test]
+    fn sprites_8x16() {
+        let (mut ppu, cassette, mut bus) = new_sprite_test();
+        bus.cpu_write(0x2000, 0x20);
+
+        ppu.oam[0..8].copy_from_slice(&[
+            9, 0x05, 0x00, 0,
+            9, 0x05, 0x80, 8, // Flipped vertically: the bottom tile comes at the top
+        ]);
+        run_frame(&mut ppu, &cassette, &mut bus);
+
+        assert_eq!(ppu.pixels[17][0], 0x0F);
+        assert_eq!(ppu.pixels[18][0], 0x30);
+        assert_eq!(ppu.pixels[17][8], 0x30);
+        assert_eq!(ppu.pixels[18][8], 0x0F);
+    }
+
+    // Position of the dot where sprite 0 hits in the next frame
+    fn find_sprite_zero_hit(ppu: &mut Ppu, cassette: &Cassette, bus: &mut PpuRegisterBus) -> Option<(usize, usize)> {
+        run_frame(ppu, cassette, bus);
+        run_until(ppu, cassette, bus, 0);
+        while ppu.scanline() < 240 {
+            let position = (ppu.scanline(), ppu.dot());
+            ppu.tick(cassette, bus);
+            if bus.status() & STATUS_SPRITE_ZERO_HIT != 0 {
+                return Some(position)
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn sprite_zero_hit() {
+        let (mut ppu, cassette, mut bus) = new_background_test();
+        ppu.oam = [0xFF; OAM_SIZE];
+        bus.cpu_write(0x2001, 0x1E);
+
+        // Tile 3 is opaque all over, and so is the background except for the left tiles.
+        ppu.oam[0..4].copy_from_slice(&[49, 3, 0x20, 100]);
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), Some((50, 101)));
+        // The flag stays until the pre-render line.
+        run_until(&mut ppu, &cassette, &mut bus, 250);
+        assert_eq!(bus.status() & STATUS_SPRITE_ZERO_HIT, STATUS_SPRITE_ZERO_HIT);
+        run_until(&mut ppu, &cassette, &mut bus, 0);
+        assert_eq!(bus.status() & STATUS_SPRITE_ZERO_HIT, 0);
+
+        // Only sprite 0 hits.
+        ppu.oam[0..8].copy_from_slice(&[0xFF, 3, 0x00, 100, 49, 3, 0x00, 100]);
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), None);
+
+        // Transparent background pixels at columns 1-6 of the left tiles
+        ppu.oam[0..4].copy_from_slice(&[49, 3, 0x00, 0]);
+        ppu.oam[4] = 0xFF;
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), Some((50, 1)));
+        ppu.oam[3] = 1;
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), Some((50, 8)));
+
+        // Leftmost 8 pixels hidden by PPUMASK
+        bus.cpu_write(0x2001, 0x1A);
+        ppu.oam[3] = 0;
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), None);
+        bus.cpu_write(0x2001, 0x1C);
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), None);
+
+        // Never at X=255
+        bus.cpu_write(0x2001, 0x1E);
+        ppu.oam[3] = 255;
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), None);
+        ppu.oam[3] = 254;
+        assert_eq!(find_sprite_zero_hit(&mut ppu, &cassette, &mut bus), Some((50, 255)));
+    }
+
+    #[test]
+    fn sprite_overflow() {
+        let (mut ppu, cassette, mut bus) = new_sprite_test();
+        for i in 0..8 {
+            ppu.oam[i * 4..i * 4 + 4].copy_from_slice(&[99, 3, 0x00, i as u8 * 8]);
+        }
+
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, 0);
+
+        // The 9th sprite. The flag is set when the line before the sprites is evaluated at dot 257.
+        ppu.oam[32..36].copy_from_slice(&[104, 3, 0x00, 0]);
+        run_until(&mut ppu, &cassette, &mut bus, 104);
+        while ppu.dot() < 257 {
+            ppu.tick(&cassette, &mut bus);
+        }
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, 0);
+        ppu.tick(&cassette, &mut bus);
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
+        run_until(&mut ppu, &cassette, &mut bus, 0);
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, 0);
+
+        // The hardware bug misses the 10th sprite after a sprite on another line,
+        // because it checks the tile number as Y.
+        ppu.oam[32..40].copy_from_slice(&[0xFF, 3, 0x00, 0, 99, 3, 0x00, 0]);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, 0);
+
+        // And finds a false one whose tile number looks like Y.
+        ppu.oam[32..40].copy_from_slice(&[0xFF, 3, 0x00, 0, 0xFF, 99, 0x00, 0]);
+        run_frame(&mut ppu, &cassette, &mut bus);
+        assert_eq!(bus.status() & STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_OVERFLOW);
     }
 }
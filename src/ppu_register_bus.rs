@@ -1,96 +1,392 @@
 use super::ppu::Register;
 
-pub enum PpuDataStatus {
-    None,
-    Read,
-    Written,
-}
+/*
+ * PPU registers as the CPU sees them.
+ * https://wiki.nesdev.com/w/index.php/PPU_registers
+ *
+ * The PPU uses them as its settings. Accesses from the CPU go through Ppu::cpu_read and
+ * Ppu::cpu_write, which handle OAMDATA, PPUDATA and PPUADDR against OAM and VRAM.
+ */
+
+// PPUCTRL
+const CTRL_NAMETABLE: u8 = 0b0000_0011;
+const CTRL_INCREMENT: u8 = 0b0000_0100;
+const CTRL_SPRITE_PATTERN_TABLE: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_PATTERN_TABLE: u8 = 0b0001_0000;
+const CTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+const CTRL_NMI_ENABLE: u8 = 0b1000_0000;
+
+// PPUMASK
+const MASK_GRAYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const MASK_EMPHASIS: u8 = 0b1110_0000;
+
+// PPUSTATUS
+pub const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+pub const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+pub const STATUS_VBLANK: u8 = 0b1000_0000;
+const STATUS_FLAGS: u8 = STATUS_SPRITE_OVERFLOW | STATUS_SPRITE_ZERO_HIT | STATUS_VBLANK;
 
 pub struct PpuRegisterBus {
-    ppu_addr_higher: Option<u8>,
-    ppu_addr: Option<u16>,
-    ppu_data: u8,
-    ppu_data_status: PpuDataStatus,
+    ppu_ctrl: u8,
+    ppu_mask: u8,
+    ppu_status: u8, // Only the top 3 bits
+    oam_addr: u8,
+    oam_dma: Option<u8>, // The page written to OAMDMA, until the CPU starts the transfer
+    // Temporary VRAM address (loopy t) and fine X scroll (loopy x)
+    // https://wiki.nesdev.com/w/index.php/PPU_scrolling
+    temp_addr: u16,
+    fine_x: u8,
+    // https://wiki.nesdev.com/w/index.php/PPU_registers#The_PPUDATA_read_buffer_(post-fetch)
+    // Reading PPUDATA returns the buffer, which the PPU then fills with the byte at the next address.
+    read_buffer: u8,
+    // Shared by PPUSCROLL and PPUADDR. false means the next write is the first one.
+    write_toggle: bool,
+    // Reading a write-only register returns the last value written to any register.
+    latch: u8,
+}
+
+impl Default for PpuRegisterBus {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PpuRegisterBus {
     pub fn new() -> Self {
         Self {
-            ppu_addr_higher: None,
-            ppu_addr: None,
-            ppu_data: 0,
-            ppu_data_status: PpuDataStatus::None,
+            ppu_ctrl: 0,
+            ppu_mask: 0,
+            ppu_status: 0,
+            oam_addr: 0,
+            oam_dma: None,
+            temp_addr: 0,
+            fine_x: 0,
+            read_buffer: 0,
+            write_toggle: false,
+            latch: 0,
         }
     }
 
+    // The reset button clears PPUCTRL, PPUMASK, PPUSCROLL, the write toggle and the PPUDATA buffer.
+    pub fn reset(&mut self) {
+        self.ppu_ctrl = 0;
+        self.ppu_mask = 0;
+        self.temp_addr = 0;
+        self.fine_x = 0;
+        self.write_toggle = false;
+        self.read_buffer = 0;
+    }
+
     pub fn cpu_read(&mut self, addr: u16) -> u8 {
         match addr.into() {
-            Register::PPUCTRL => { todo!("Setting PPUCTRL is not implemented") },
-            Register::PPUMASK => { todo!("Setting PPUMASK is not implemented") },
-            Register::PPUSTATUS => { todo!("Setting PPUSTATUS is not implemented") },
-            Register::OAMADDR => { todo!("Setting OAMADDR is not implemented") },
-            Register::OAMDATA => { todo!("Setting OAMDATA is not implemented") },
-            Register::PPUSCROLL => { todo!("Setting PPUSCROLL is not implemented") },
-            Register::PPUADDR => panic!("Forbidden to read PPUADDR from CPU"),
-            Register::PPUDATA => {
-                self.ppu_data_status = PpuDataStatus::Read;
-                self.ppu_data
+            Register::PPUSTATUS => {
+                let data = self.ppu_status | (self.latch & !STATUS_FLAGS);
+                self.ppu_status &= !STATUS_VBLANK;
+                self.write_toggle = false;
+                self.latch = data;
+                data
             },
-            Register::OAMDMA => { todo!("Setting OAMDMA is not implemented") },
+            // Write-only registers, and OAMDATA and PPUDATA, whose data comes from the PPU
+            _ => self.latch,
         }
     }
 
     pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        self.latch = data;
+
         match addr.into() {
-            Register::PPUCTRL => { todo!("Setting PPUCTRL is not implemented"); },
-            Register::PPUMASK => { todo!("Setting PPUMASK is not implemented"); },
-            Register::PPUSTATUS => { todo!("Setting PPUSTATUS is not implemented"); },
-            Register::OAMADDR => { todo!("Setting OAMADDR is not implemented"); },
-            Register::OAMDATA => { todo!("Setting OAMDATA is not implemented"); },
-            Register::PPUSCROLL => { todo!("Setting PPUSCROLL is not implemented"); },
-            Register::PPUADDR => {
-                match (self.ppu_addr_higher, self.ppu_addr) {
-                    (None, _) => self.ppu_addr_higher = Some(data),
-                    (Some(higher), _) => {
-                        self.ppu_addr = Some((higher as u16) << 8 | data as u16);
-                        self.ppu_addr_higher = None;
-                    },
+            Register::PPUCTRL => {
+                self.ppu_ctrl = data;
+                // t: ...GH.. ........ <- d: ......GH
+                self.temp_addr = (self.temp_addr & !0x0C00) | ((data & CTRL_NAMETABLE) as u16) << 10;
+            },
+            Register::PPUMASK => self.ppu_mask = data,
+            Register::PPUSTATUS => {},
+            Register::OAMADDR => self.oam_addr = data,
+            Register::OAMDATA => self.oam_addr = self.oam_addr.wrapping_add(1),
+            Register::PPUSCROLL => {
+                if self.write_toggle {
+                    // t: FGH..AB CDE..... <- d: ABCDEFGH
+                    self.temp_addr = (self.temp_addr & !0x73E0)
+                        | ((data & 0x07) as u16) << 12
+                        | ((data & 0xF8) as u16) << 2;
+                } else {
+                    // t: ....... ...ABCDE <- d: ABCDE...
+                    // x:              FGH <- d: .....FGH
+                    self.temp_addr = (self.temp_addr & !0x001F) | (data >> 3) as u16;
+                    self.fine_x = data & 0x07;
                 }
+                self.write_toggle = !self.write_toggle;
             },
-            Register::PPUDATA => {
-                self.ppu_data = data;
-                self.ppu_data_status = PpuDataStatus::Written;
+            Register::PPUADDR => {
+                if self.write_toggle {
+                    // t: ....... ABCDEFGH <- d: ABCDEFGH, then the PPU copies t into v.
+                    self.temp_addr = (self.temp_addr & 0xFF00) | data as u16;
+                } else {
+                    // t: .CDEFGH ........ <- d: ..CDEFGH, and bit 14 is cleared.
+                    self.temp_addr = (self.temp_addr & 0x00FF) | ((data & 0x3F) as u16) << 8;
+                }
+                self.write_toggle = !self.write_toggle;
             },
-            Register::OAMDMA => { todo!("Setting OAMDMA is not implemented"); },
+            Register::PPUDATA => {},
         }
     }
 
-    pub fn ppu_read(&mut self, r: Register) -> Option<u16> {
-        match r {
-            Register::PPUADDR => {
-                let addr = self.ppu_addr;
-                self.ppu_addr = None;
-                addr
-            },
-            Register::PPUDATA => {
-                self.ppu_data_status = PpuDataStatus::None;
-                Some(self.ppu_data as u16)
-            },
-            _ => {None},
-        }
+    // Put `data` on the bus as the result of a read, and return it.
+    pub fn drive_latch(&mut self, data: u8) -> u8 {
+        self.latch = data;
+        data
     }
 
-    pub fn ppu_write(&mut self, r: Register, data: u8) {
-        match r {
-            Register::PPUDATA => {
-                self.ppu_data = data;
-                self.ppu_data_status = PpuDataStatus::None;
-            },
-            _ => panic!("Forbidden to write data into {:04X} from PPU", usize::from(r)),
+    pub fn latch(&self) -> u8 {
+        self.latch
+    }
+
+    // Fill the PPUDATA read buffer, and return what it had.
+    pub fn swap_read_buffer(&mut self, data: u8) -> u8 {
+        std::mem::replace(&mut self.read_buffer, data)
+    }
+
+    // A write to OAMDMA ($4014). It is a CPU register rather than a PPU one, so the PPU data bus is left
+    // alone; this only holds the page until the CPU starts the transfer.
+    pub fn request_oam_dma(&mut self, page: u8) {
+        self.oam_dma = Some(page);
+    }
+
+    // The page XX00 to copy into OAM, once per write to OAMDMA.
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+
+    pub fn oam_addr(&self) -> u8 {
+        self.oam_addr
+    }
+
+    // A write to OAMDATA while rendering bumps the sprite index in OAMADDR rather than the byte.
+    pub fn increment_oam_sprite(&mut self) {
+        self.oam_addr = self.oam_addr.wrapping_add(4);
+    }
+
+    // OAMADDR is cleared on each dot of sprite tile loading while rendering.
+    pub fn reset_oam_addr(&mut self) {
+        self.oam_addr = 0;
+    }
+
+    // Set or clear flags of PPUSTATUS.
+    pub fn set_status(&mut self, flags: u8, value: bool) {
+        if value {
+            self.ppu_status |= flags & STATUS_FLAGS;
+        } else {
+            self.ppu_status &= !flags;
         }
     }
 
-    pub fn ppu_data_status(&self) -> &PpuDataStatus {
-        &self.ppu_data_status
+    pub fn status(&self) -> u8 {
+        self.ppu_status
+    }
+
+    // The PPU pulls /NMI while both VBlank and the NMI enable are set. Enabling NMI during VBlank
+    // makes another edge, so toggling PPUCTRL bit 7 can raise more than one NMI in a frame.
+    pub fn nmi_output(&self) -> bool {
+        self.nmi_enabled() && self.ppu_status & STATUS_VBLANK != 0
+    }
+
+    pub fn base_nametable_addr(&self) -> u16 {
+        0x2000 + 0x0400 * (self.ppu_ctrl & CTRL_NAMETABLE) as u16
+    }
+
+    // How far PPUADDR goes after an access to PPUDATA: 1 (across) or 32 (down).
+    pub fn vram_increment(&self) -> u16 {
+        if self.ppu_ctrl & CTRL_INCREMENT == 0 { 1 } else { 32 }
+    }
+
+    // Ignored for 8x16 sprites, which select the table by the tile number.
+    pub fn sprite_pattern_table_addr(&self) -> u16 {
+        if self.ppu_ctrl & CTRL_SPRITE_PATTERN_TABLE == 0 { 0x0000 } else { 0x1000 }
+    }
+
+    pub fn background_pattern_table_addr(&self) -> u16 {
+        if self.ppu_ctrl & CTRL_BACKGROUND_PATTERN_TABLE == 0 { 0x0000 } else { 0x1000 }
+    }
+
+    pub fn sprite_height(&self) -> usize {
+        if self.ppu_ctrl & CTRL_SPRITE_SIZE == 0 { 8 } else { 16 }
+    }
+
+    pub fn nmi_enabled(&self) -> bool {
+        self.ppu_ctrl & CTRL_NMI_ENABLE != 0
+    }
+
+    pub fn grayscale(&self) -> bool {
+        self.ppu_mask & MASK_GRAYSCALE != 0
+    }
+
+    pub fn show_background_left(&self) -> bool {
+        self.ppu_mask & MASK_BACKGROUND_LEFT != 0
+    }
+
+    pub fn show_sprites_left(&self) -> bool {
+        self.ppu_mask & MASK_SPRITES_LEFT != 0
+    }
+
+    pub fn show_background(&self) -> bool {
+        self.ppu_mask & MASK_BACKGROUND != 0
+    }
+
+    pub fn show_sprites(&self) -> bool {
+        self.ppu_mask & MASK_SPRITES != 0
+    }
+
+    pub fn rendering_enabled(&self) -> bool {
+        self.show_background() || self.show_sprites()
+    }
+
+    // Bit 0: red, 1: green, 2: blue
+    pub fn emphasis(&self) -> u8 {
+        (self.ppu_mask & MASK_EMPHASIS) >> 5
+    }
+
+    pub fn write_toggle(&self) -> bool {
+        self.write_toggle
+    }
+
+    pub fn temp_addr(&self) -> u16 {
+        self.temp_addr
+    }
+
+    pub fn fine_x(&self) -> u8 {
+        self.fine_x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PpuRegisterBus;
+    use super::STATUS_SPRITE_ZERO_HIT;
+    use super::STATUS_VBLANK;
+
+    #[test]
+    fn ppu_ctrl() {
+        let mut bus = PpuRegisterBus::new();
+        assert_eq!(bus.base_nametable_addr(), 0x2000);
+        assert_eq!(bus.vram_increment(), 1);
+        assert!(!bus.nmi_enabled());
+
+        bus.cpu_write(0x2000, 0b1011_1110);
+        assert_eq!(bus.base_nametable_addr(), 0x2800);
+        assert_eq!(bus.vram_increment(), 32);
+        assert_eq!(bus.sprite_pattern_table_addr(), 0x1000);
+        assert_eq!(bus.background_pattern_table_addr(), 0x1000);
+        assert_eq!(bus.sprite_height(), 16);
+        assert!(bus.nmi_enabled());
+
+        bus.reset();
+        assert!(!bus.nmi_enabled());
+    }
+
+    #[test]
+    fn ppu_mask() {
+        let mut bus = PpuRegisterBus::new();
+        assert!(!bus.rendering_enabled());
+
+        bus.cpu_write(0x2001, 0b1010_1001);
+        assert!(bus.grayscale());
+        assert!(bus.show_background());
+        assert!(!bus.show_background_left());
+        assert!(!bus.show_sprites());
+        assert!(bus.rendering_enabled());
+        assert_eq!(bus.emphasis(), 0b101);
+    }
+
+    #[test]
+    fn ppu_status() {
+        let mut bus = PpuRegisterBus::new();
+        bus.set_status(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT, true);
+        bus.cpu_write(0x2001, 0x1F); // latch
+
+        // Reading clears VBlank and the write toggle. The lower bits are stale bus contents.
+        bus.cpu_write(0x2006, 0x3F);
+        assert_eq!(bus.cpu_read(0x2002), 0xDF);
+        assert_eq!(bus.cpu_read(0x2002), 0x5F);
+        bus.cpu_write(0x2006, 0x21);
+        bus.cpu_write(0x2006, 0x08);
+        assert_eq!(bus.temp_addr(), 0x2108);
+        assert!(!bus.write_toggle());
+
+        bus.set_status(STATUS_SPRITE_ZERO_HIT, false);
+        assert_eq!(bus.status(), 0);
+    }
+
+    #[test]
+    fn nmi_output() {
+        let mut bus = PpuRegisterBus::new();
+        bus.set_status(STATUS_VBLANK, true);
+        assert!(!bus.nmi_output());
+        bus.cpu_write(0x2000, 0x80);
+        assert!(bus.nmi_output());
+        bus.cpu_write(0x2000, 0x00);
+        assert!(!bus.nmi_output());
+        bus.cpu_write(0x2000, 0x80);
+        assert!(bus.nmi_output());
+
+        bus.cpu_read(0x2002);
+        assert!(!bus.nmi_output());
+    }
+
+    #[test]
+    fn write_toggle() {
+        let mut bus = PpuRegisterBus::new();
+        bus.cpu_write(0x2005, 0x7D); // coarse X 15, fine X 5
+        bus.cpu_write(0x2005, 0x5E); // coarse Y 11, fine Y 6
+        assert_eq!(bus.temp_addr(), 0x616F);
+        assert_eq!(bus.fine_x(), 5);
+
+        // PPUSCROLL and PPUADDR share the toggle.
+        bus.cpu_write(0x2006, 0x21);
+        bus.cpu_write(0x2005, 0x00);
+        assert_eq!(bus.temp_addr(), 0x000F);
+        assert!(!bus.write_toggle());
+    }
+
+    #[test]
+    fn temp_addr() {
+        let mut bus = PpuRegisterBus::new();
+        bus.cpu_write(0x2000, 0x03);
+        assert_eq!(bus.temp_addr(), 0x0C00);
+        bus.cpu_write(0x2006, 0xFF); // Bit 14 is cleared.
+        bus.cpu_write(0x2006, 0x12);
+        assert_eq!(bus.temp_addr(), 0x3F12);
+        bus.cpu_write(0x2000, 0x00);
+        assert_eq!(bus.temp_addr(), 0x3312);
+    }
+
+    #[test]
+    fn oam() {
+        let mut bus = PpuRegisterBus::new();
+        bus.cpu_write(0x2003, 0xFF);
+        bus.cpu_write(0x2004, 0x12);
+        bus.cpu_write(0x2004, 0x34);
+        assert_eq!(bus.oam_addr(), 0x01);
+
+        bus.request_oam_dma(0x02);
+        assert_eq!(bus.latch(), 0x34);
+        assert_eq!(bus.take_oam_dma(), Some(0x02));
+        assert_eq!(bus.take_oam_dma(), None);
+    }
+
+    #[test]
+    fn latch() {
+        let mut bus = PpuRegisterBus::new();
+        assert_eq!(bus.drive_latch(0x56), 0x56);
+        assert_eq!(bus.cpu_read(0x2000), 0x56);
+
+        assert_eq!(bus.swap_read_buffer(0x12), 0x00);
+        assert_eq!(bus.swap_read_buffer(0x34), 0x12);
+        bus.reset();
+        assert_eq!(bus.swap_read_buffer(0x00), 0x00);
     }
 }
@@ -0,0 +1,230 @@
+use std::fmt::{self, Formatter, Display};
+
+use super::bus::Bus;
+use super::cpu::Cpu;
+use super::disasm::{self, Disassembly};
+use super::instruction::{Addressing, Opcode};
+use super::nes::Nes;
+
+/*
+ * Trace in the format of nestest.log, which is produced by Nintendulator.
+ * https://www.qmtpro.com/~nes/misc/nestest.log
+ *
+ * C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+ */
+
+// nestest runs all of its tests without the PPU when it starts at $C000.
+pub const NESTEST_START: u16 = 0xC000;
+// Nintendulator starts with I and the unused bit set in P.
+const NESTEST_STATUS: u8 = 0x24;
+
+pub fn start_nestest(cpu: &mut Cpu, nes: &mut Nes) {
+    cpu.power_on(nes);
+    cpu.set_pc(NESTEST_START);
+    cpu.set_status(NESTEST_STATUS);
+}
+
+// The trace line of the instruction at PC, before it is executed.
+pub fn nestest_line(cpu: &Cpu, nes: &Nes) -> String {
+    let instruction = disasm::disassemble(cpu.pc(), |addr| nes.peek(addr));
+
+    let bytes: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let marker = if instruction.is_unofficial() { '*' } else { ' ' };
+
+    format!("{:04X}  {:<9}{}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            instruction.addr, bytes.join(" "), marker, annotate(&instruction, cpu, nes),
+            cpu.a(), cpu.x(), cpu.y(), cpu.status(), cpu.s(),
+            nes.ppu.scanline(), nes.ppu.dot(), cpu.cycles())
+}
+
+// Memory operands are followed by the addresses they refer to and their current values,
+// like `LDA ($89),Y = 0300 @ 0300 = 89`.
+fn annotate(instruction: &Disassembly, cpu: &Cpu, nes: &Nes) -> String {
+    let operand = instruction.operand();
+
+    let annotation = match instruction.mode {
+        Addressing::ZeroPage => format!(" = {:02X}", nes.peek(operand)),
+        Addressing::ZeroPageX => {
+            let addr = (operand as u8).wrapping_add(cpu.x());
+            format!(" @ {:02X} = {:02X}", addr, nes.peek(addr as u16))
+        },
+        Addressing::ZeroPageY => {
+            let addr = (operand as u8).wrapping_add(cpu.y());
+            format!(" @ {:02X} = {:02X}", addr, nes.peek(addr as u16))
+        },
+        Addressing::Absolute if instruction.branch_target().is_some() => String::new(),
+        Addressing::Absolute => format!(" = {:02X}", nes.peek(operand)),
+        Addressing::AbsoluteX => {
+            let addr = operand.wrapping_add(cpu.x() as u16);
+            format!(" @ {:04X} = {:02X}", addr, nes.peek(addr))
+        },
+        Addressing::AbsoluteY => {
+            let addr = operand.wrapping_add(cpu.y() as u16);
+            format!(" @ {:04X} = {:02X}", addr, nes.peek(addr))
+        },
+        Addressing::Indirect => {
+            // The high byte of the target doesn't carry into the next page.
+            let high = (operand & 0xff00) | (operand as u8).wrapping_add(1) as u16;
+            let target = (nes.peek(high) as u16) << 8 | nes.peek(operand) as u16;
+            format!(" = {:04X}", target)
+        },
+        Addressing::IndexedIndirect => {
+            let pointer = (operand as u8).wrapping_add(cpu.x());
+            let addr = peek_zero_page_word(nes, pointer);
+            format!(" @ {:02X} = {:04X} = {:02X}", pointer, addr, nes.peek(addr))
+        },
+        Addressing::IndirectIndexed => {
+            let base = peek_zero_page_word(nes, operand as u8);
+            let addr = base.wrapping_add(cpu.y() as u16);
+            format!(" = {:04X} @ {:04X} = {:02X}", base, addr, nes.peek(addr))
+        },
+        _ => String::new(),
+    };
+
+    // Nintendulator calls ISC "ISB".
+    let mnemonic = match instruction.opcode {
+        Opcode::ISC => "ISB".to_string(),
+        _ => instruction.mnemonic(),
+    };
+
+    let operand = instruction.format_operand();
+    if operand.is_empty() {
+        mnemonic
+    } else {
+        format!("{} {}{}", mnemonic, operand, annotation)
+    }
+}
+
+fn peek_zero_page_word(nes: &Nes, addr: u8) -> u16 {
+    (nes.peek(addr.wrapping_add(1) as u16) as u16) << 8 | nes.peek(addr as u16) as u16
+}
+
+#[derive(Debug)]
+pub struct Divergence {
+    pub line: usize, // 1-origin
+    pub expected: String,
+    pub actual: String,
+    pub previous: Option<String>,
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let column = self.expected.chars().zip(self.actual.chars())
+            .take_while(|(e, a)| e == a)
+            .count();
+
+        writeln!(f, "Diverged from the golden log at line {}", self.line)?;
+        if let Some(previous) = &self.previous {
+            writeln!(f, "  previous: {}", previous)?;
+        }
+        writeln!(f, "  expected: {}", self.expected)?;
+        writeln!(f, "  actual:   {}", self.actual)?;
+        write!(f, "            {}^", " ".repeat(column))
+    }
+}
+
+// Run the CPU one instruction per line of the golden log and compare the trace with it.
+// Returns the number of lines which matched, or the first line which didn't.
+pub fn compare_with_log<'a, I>(cpu: &mut Cpu, nes: &mut Nes, golden_log: I) -> Result<usize, Divergence>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut previous = None;
+    let mut matched = 0;
+
+    for expected in golden_log.map(|line| line.trim_end()).filter(|line| !line.is_empty()) {
+        let actual = nestest_line(cpu, nes);
+        if actual != expected {
+            return Err(Divergence {
+                line: matched + 1,
+                expected: expected.to_string(),
+                actual,
+                previous,
+            })
+        }
+
+        cpu.tick(nes);
+        previous = Some(actual);
+        matched += 1;
+    }
+
+    Ok(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare_with_log;
+    use super::nestest_line;
+    use super::start_nestest;
+    use crate::cpu::Cpu;
+    use crate::nes::Nes;
+
+    // LDX #$05; STX $10; LDA $0B,X; NOP $A9 (unofficial); JMP $C000
+    // The 16 KiB test PRG ROM is mirrored at $C000.
+    const PROGRAM: [u8; 11] = [0xA2, 0x05, 0x86, 0x10, 0xB5, 0x0B, 0x04, 0xA9, 0x4C, 0x00, 0xC0];
+
+    const LOG: [&str; 5] = [
+        "C000  A2 05     LDX #$05                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        "C002  86 10     STX $10 = 00                    A:00 X:05 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+        "C004  B5 0B     LDA $0B,X @ 10 = 05             A:00 X:05 Y:00 P:24 SP:FD PPU:  0, 36 CYC:12",
+        "C006  04 A9    *NOP $A9 = 00                    A:05 X:05 Y:00 P:24 SP:FD PPU:  0, 48 CYC:16",
+        "C008  4C 00 C0  JMP $C000                       A:05 X:05 Y:00 P:24 SP:FD PPU:  0, 57 CYC:19",
+    ];
+
+    // LDA #$02; STA $4015; STA $4004, as at the end of nestest
+    const APU_PROGRAM: [u8; 8] = [0xA9, 0x02, 0x8D, 0x15, 0x40, 0x8D, 0x04, 0x40];
+
+    const APU_LOG: [&str; 3] = [
+        "C000  A9 02     LDA #$02                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        "C002  8D 15 40  STA $4015 = FF                  A:02 X:00 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+        "C005  8D 04 40  STA $4004 = FF                  A:02 X:00 Y:00 P:24 SP:FD PPU:  0, 39 CYC:13",
+    ];
+
+    fn new_nestest_cpu() -> (Cpu, Nes) {
+        new_nestest_cpu_with(&PROGRAM)
+    }
+
+    fn new_nestest_cpu_with(program: &[u8]) -> (Cpu, Nes) {
+        let mut prg_rom = program.to_vec();
+        prg_rom.resize(0x4000, 0xEA);
+        // RESET vector
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0xC0;
+
+        let mut cpu = Cpu::new();
+        let mut nes = Nes::new_for_test(prg_rom);
+        start_nestest(&mut cpu, &mut nes);
+        (cpu, nes)
+    }
+
+    #[test]
+    fn trace_line() {
+        let (mut cpu, mut nes) = new_nestest_cpu();
+        for line in LOG.iter() {
+            assert_eq!(nestest_line(&cpu, &nes), *line);
+            cpu.tick(&mut nes);
+        }
+    }
+
+    #[test]
+    fn golden_log() {
+        let (mut cpu, mut nes) = new_nestest_cpu();
+        assert_eq!(compare_with_log(&mut cpu, &mut nes, LOG.iter().copied()).unwrap(), 5);
+
+        let mut log = LOG.to_vec();
+        let wrong = LOG[2].replace("X:05", "X:06");
+        log[2] = &wrong;
+        let (mut cpu, mut nes) = new_nestest_cpu();
+        let divergence = compare_with_log(&mut cpu, &mut nes, log.into_iter()).unwrap_err();
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.expected, wrong);
+        assert_eq!(divergence.actual, LOG[2]);
+        assert_eq!(divergence.previous.as_deref(), Some(LOG[1]));
+    }
+
+    #[test]
+    fn golden_log_apu_registers() {
+        let (mut cpu, mut nes) = new_nestest_cpu_with(&APU_PROGRAM);
+        assert_eq!(compare_with_log(&mut cpu, &mut nes, APU_LOG.iter().copied()).unwrap(), 3);
+    }
+}
@@ -0,0 +1,127 @@
+/*
+ * Klaus Dormann's 6502 functional test
+ * https://github.com/Klaus2m5/6502_65C02_functional_tests
+ *
+ * The binary is not in the repository. Fetch it into tests/roms as described in tests/roms/README.md,
+ * or set KLAUS_FUNCTIONAL_TEST to its path. Without it, the test reports SKIP and passes.
+ *
+ * The standard build traps at `success` at 0x3469. The 2A03 has no decimal mode, so a build with
+ * `disable_decimal = 1` is needed to get there; set KLAUS_SUCCESS_PC to the address of `success` in
+ * its listing.
+ */
+use std::env;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+
+use rust_nes::bus::FlatMemory;
+use rust_nes::cpu::Cpu;
+
+const DEFAULT_PATH: &str = "tests/roms/6502_functional_test.bin";
+const LOAD_ADDRESS: u16 = 0x0000;
+const START_PC: u16 = 0x0400;
+const SUCCESS_PC: u16 = 0x3469;
+
+// The whole test takes about 30 million instructions.
+const MAX_INSTRUCTIONS: usize = 100_000_000;
+
+// Load a flat binary into 64 KiB of RAM and run it from `start` until it traps.
+// Returns the PC of the trap, or None if it didn't trap within `max_instructions`.
+fn run_until_trap(binary: &[u8], load_address: u16, start: u16, max_instructions: usize) -> (Cpu, Option<u16>) {
+    let mut memory = FlatMemory::new();
+    memory.load(load_address, binary);
+
+    let mut cpu = Cpu::new();
+    cpu.set_pc(start);
+
+    for _ in 0..max_instructions {
+        let pc = cpu.pc();
+        cpu.tick(&mut memory);
+        // A trap is an instruction which jumps or branches to itself, such as `JMP *` or `BNE *`.
+        if cpu.pc() == pc {
+            return (cpu, Some(pc))
+        }
+    }
+
+    (cpu, None)
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass(u16),
+    Fail(u16),
+    NoTrap,
+}
+
+impl Outcome {
+    fn new(trap: Option<u16>, success_pc: u16) -> Self {
+        match trap {
+            Some(pc) if pc == success_pc => Outcome::Pass(pc),
+            Some(pc) => Outcome::Fail(pc),
+            None => Outcome::NoTrap,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Outcome::Pass(pc) => write!(f, "PASS: trapped at 0x{:04X}", pc),
+            Outcome::Fail(pc) => write!(f, "FAIL: trapped at 0x{:04X}", pc),
+            Outcome::NoTrap => write!(f, "FAIL: no trap within {} instructions", MAX_INSTRUCTIONS),
+        }
+    }
+}
+
+fn parse_address(s: &str) -> u16 {
+    let s = s.trim_start_matches("0x").trim_start_matches('$');
+    u16::from_str_radix(s, 16).unwrap_or_else(|_| panic!("Invalid address: {}", s))
+}
+
+#[test]
+fn functional_test() {
+    let path = env::var("KLAUS_FUNCTIONAL_TEST").map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(DEFAULT_PATH));
+    let binary = match fs::read(&path) {
+        Ok(binary) => binary,
+        Err(e) => {
+            eprintln!("SKIP: {}: {}", path.display(), e);
+            return
+        },
+    };
+    let success_pc = env::var("KLAUS_SUCCESS_PC").map(|s| parse_address(&s)).unwrap_or(SUCCESS_PC);
+
+    let (cpu, trap) = run_until_trap(&binary, LOAD_ADDRESS, START_PC, MAX_INSTRUCTIONS);
+    let outcome = Outcome::new(trap, success_pc);
+    eprintln!("{}", outcome);
+    if outcome != Outcome::Pass(success_pc) {
+        cpu.dump();
+        panic!("{}", outcome);
+    }
+}
+
+#[test]
+fn harness_detects_trap() {
+    // LDX #$05; DEX; BNE -3; JMP $0205
+    let program = [0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0x4C, 0x05, 0x02];
+    let (_, trap) = run_until_trap(&program, 0x0200, 0x0200, 100);
+    assert_eq!(trap, Some(0x0205));
+
+    // BNE * while Z is clear
+    let (_, trap) = run_until_trap(&[0xD0, 0xFE], 0x0200, 0x0200, 100);
+    assert_eq!(trap, Some(0x0200));
+}
+
+#[test]
+fn harness_reports_outcome() {
+    assert_eq!(Outcome::new(Some(0x3469), 0x3469).to_string(), "PASS: trapped at 0x3469");
+    assert_eq!(Outcome::new(Some(0x0200), 0x3469).to_string(), "FAIL: trapped at 0x0200");
+    assert_eq!(Outcome::new(None, 0x3469), Outcome::NoTrap);
+}
+
+#[test]
+fn harness_gives_up() {
+    // A loop of two instructions isn't a trap. NOP; JMP $0200
+    let (_, trap) = run_until_trap(&[0xEA, 0x4C, 0x00, 0x02], 0x0200, 0x0200, 100);
+    assert_eq!(trap, None);
+}